@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+
+use candid::Encode;
+use ic_cdk::api::{data_certificate, set_certified_data};
+use ic_certification::{labeled, labeled_hash, AsHashTree, Hash, HashTree, RbTree};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::model::{CertifiedBadgeState, StoredBadge};
+
+/// The label of the subtree holding the badge hashes in the certified tree.
+const BADGES_LABEL: &[u8] = b"badges";
+
+thread_local! {
+    /// Maps the big-endian bytes of every badge ID to the hash of its certified state.
+    /// Like the search index it is kept on the heap and rebuilt in batches after every upgrade.
+    static CERTIFIED_BADGES: RefCell<RbTree<Vec<u8>, Hash>> = RefCell::default();
+}
+
+/// state_hash returns the SHA-256 hash of the Candid encoding of the state,
+/// which clients recompute to check it against the witness.
+pub fn state_hash(state: &CertifiedBadgeState) -> Hash {
+    let bytes = Encode!(state).expect("Could not encode badge state.");
+    Sha256::digest(bytes).into()
+}
+
+/// certify_badge puts the hash of the badge state into the tree and certifies the new root.
+/// It must only be called from update calls, timers, `init` and `post_upgrade`.
+pub fn certify_badge(badge: &StoredBadge) {
+    certify_badges(std::slice::from_ref(badge));
+}
+
+/// certify_badges puts the hashes of all badge states into the tree and certifies the new root once.
+pub fn certify_badges(badges: &[StoredBadge]) {
+    CERTIFIED_BADGES.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        for badge in badges {
+            let hash = state_hash(&CertifiedBadgeState::from(badge));
+            tree.insert(badge.id.to_be_bytes().to_vec(), hash);
+        }
+        set_root(&tree);
+    });
+}
+
+/// reset removes all badges from the tree, so that they can be certified again from scratch.
+pub fn reset() {
+    CERTIFIED_BADGES.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        *tree = RbTree::new();
+        set_root(&tree);
+    });
+}
+
+fn set_root(tree: &RbTree<Vec<u8>, Hash>) {
+    set_certified_data(&labeled_hash(BADGES_LABEL, &tree.root_hash()));
+}
+
+/// witness returns the CBOR encoded hash tree proving the state of the badge,
+/// or its absence, under the path `badges/<badge ID>`.
+pub fn witness(badge_id: u128) -> Vec<u8> {
+    let tree: HashTree = CERTIFIED_BADGES
+        .with(|tree| labeled(BADGES_LABEL, tree.borrow().witness(&badge_id.to_be_bytes())));
+
+    let mut serializer = serde_cbor::Serializer::new(Vec::new());
+    serializer
+        .self_describe()
+        .expect("Could not encode witness.");
+    tree.serialize(&mut serializer)
+        .expect("Could not encode witness.");
+    serializer.into_inner()
+}
+
+/// certificate returns the system certificate of the certified root.
+/// It is only available in query calls.
+pub fn certificate() -> Option<Vec<u8>> {
+    data_certificate()
+}
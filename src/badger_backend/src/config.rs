@@ -0,0 +1,35 @@
+use std::cell::RefCell;
+
+use ic_stable_structures::StableCell;
+
+use crate::memory::{self, Memory, CONFIG_MEMORY_ID};
+use crate::model::{Config, InitArgs};
+
+thread_local! {
+    static CONFIG: RefCell<StableCell<Config, Memory>> = RefCell::new(
+        StableCell::init(memory::get(CONFIG_MEMORY_ID), Config::default())
+            .expect("Could not initialise config.")
+    );
+}
+
+/// get returns the current settings of the canister.
+pub fn get() -> Config {
+    CONFIG.with(|it| it.borrow().get().clone())
+}
+
+/// apply stores the settings passed in the arguments and keeps all others.
+pub fn apply(args: InitArgs) {
+    let mut config = get();
+    if args.administrator.is_some() {
+        config.administrator = args.administrator;
+    }
+    if args.gateway_url.is_some() {
+        config.gateway_url = args.gateway_url;
+    }
+    if args.ecdsa_key_name.is_some() {
+        config.ecdsa_key_name = args.ecdsa_key_name;
+    }
+    CONFIG.with(|it| {
+        it.borrow_mut().set(config).expect("Could not save config.");
+    });
+}
@@ -0,0 +1,235 @@
+use ic_cdk::api::{caller, id};
+use serde::Serialize;
+
+use crate::config;
+use crate::model::{
+    BadgeVerification, CertifiedBadgeState, Claim, Error, HttpRequest, HttpResponse,
+};
+use crate::util::{self, authenticated_user};
+use crate::BADGES;
+
+/// The URL of the HTTP gateway unless configured otherwise.
+/// Responses depend on the caller and are not certified, so they are served through the raw domain,
+/// which does not verify responses.
+const DEFAULT_GATEWAY_URL: &str = "https://{canisterId}.raw.icp0.io";
+
+/// PublicBadge is the verification data of a badge served over HTTP.
+/// Claims are only included if the caller is a user with access to them.
+/// The certificate and the witness are hex encoded, see `CertifiedBadgeVerification`.
+#[derive(Serialize)]
+struct PublicBadge {
+    #[serde(rename = "badge")]
+    verification: BadgeVerification,
+
+    claims: Vec<Claim>,
+
+    state: CertifiedBadgeState,
+
+    certificate: Option<String>,
+
+    witness: String,
+}
+
+/// Format is the representation of a badge requested by the URL.
+enum Format {
+    Html,
+    Json,
+}
+
+/// handle serves `/badges/{id}` as an HTML page and `/badges/{id}.json` as JSON.
+pub fn handle(request: HttpRequest) -> HttpResponse {
+    if request.method != "GET" {
+        return response(
+            405,
+            "text/plain; charset=utf-8",
+            "Method not allowed.".into(),
+        );
+    }
+
+    let path = request.url.split(['?', '#']).next().unwrap_or_default();
+    let route = parse_route(path);
+    if route.is_none() {
+        return response(404, "text/plain; charset=utf-8", "Not found.".into());
+    }
+    let (badge_id, format) = route.unwrap();
+
+    let badge = find_public_badge(badge_id);
+    match format {
+        Format::Json => match badge {
+            Ok(badge) => json(200, &badge),
+            Err(err) => json(status_code(&err), &err),
+        },
+        Format::Html => match badge {
+            Ok(badge) => response(200, "text/html; charset=utf-8", render_badge(&badge)),
+            Err(err) => response(
+                status_code(&err),
+                "text/html; charset=utf-8",
+                render_error(&err),
+            ),
+        },
+    }
+}
+
+/// badge_url returns the URL of the verification page of the badge.
+/// Appending `.json` to it gives the URL of the verification data.
+pub fn badge_url(badge_id: u128) -> String {
+    let gateway_url = config::get()
+        .gateway_url
+        .unwrap_or_else(|| DEFAULT_GATEWAY_URL.to_string());
+    format!(
+        "{}/badges/{}",
+        gateway_url
+            .trim_end_matches('/')
+            .replace("{canisterId}", &id().to_text()),
+        badge_id
+    )
+}
+
+/// parse_route returns the badge ID and the format of a badge path, or `None` for any other path.
+fn parse_route(path: &str) -> Option<(u128, Format)> {
+    let id = path.strip_prefix("/badges/")?;
+    let (id, format) = match id.strip_suffix(".json") {
+        Some(id) => (id, Format::Json),
+        None => (id, Format::Html),
+    };
+    id.parse().ok().map(|id| (id, format))
+}
+
+/// find_public_badge returns the verification data of the badge.
+/// The claims go through the same access checks and masking as `badges_get_one`,
+/// so callers without access to the badge, like anonymous browsers, never see them.
+fn find_public_badge(badge_id: u128) -> Result<PublicBadge, Error> {
+    let certified = util::verify_badge(badge_id)?;
+
+    let user = authenticated_user(caller());
+    let badge = BADGES.with(|badges| badges.borrow().get(&badge_id));
+    let claims = match (user, badge) {
+        (Some(user), Some(badge)) if user.has_badge_access(&badge) => {
+            util::clear_claims(&user, &util::hydrate_badge(&badge)).claims
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(PublicBadge {
+        verification: certified.verification,
+        claims,
+        state: certified.state,
+        certificate: certified.certificate.as_deref().map(util::to_hex),
+        witness: util::to_hex(&certified.witness),
+    })
+}
+
+fn status_code(err: &Error) -> u16 {
+    match err {
+        Error::NotFound { .. } => 404,
+        Error::Unauthorized { .. } => 401,
+        Error::Forbidden { .. } => 403,
+        Error::InvalidInput { .. } => 400,
+        Error::Conflict { .. } => 409,
+        Error::Unavailable { .. } => 503,
+    }
+}
+
+fn render_badge(badge: &PublicBadge) -> String {
+    let v = &badge.verification;
+    let state = if v.valid { "Valid" } else { "Not valid" };
+
+    let mut rows = vec![
+        ("Status", format!("{:?}", v.status)),
+        ("Issuer", v.issuer.name.clone()),
+        ("Issued at", util::format_date(v.issued_at)),
+    ];
+    if let Some(expires_at) = v.expires_at {
+        rows.push(("Expires at", util::format_date(expires_at)));
+    }
+    if let Some(revoked_at) = v.revoked_at {
+        rows.push(("Revoked at", util::format_date(revoked_at)));
+    }
+    if let Some(reason) = v.revocation_reason {
+        rows.push(("Revocation reason", format!("{:?}", reason)));
+    }
+    if let Some(provenance) = &v.provenance {
+        rows.push(("Imported from", provenance.issuer_name.clone()));
+        if let Some(credential_id) = &provenance.credential_id {
+            rows.push(("Original credential", credential_id.clone()));
+        }
+        rows.push(("Imported at", util::format_date(provenance.imported_at)));
+    }
+    for claim in badge.claims.iter() {
+        rows.push(("Claim", format!("{}: {}", claim.key, claim.value)));
+    }
+
+    let rows: String = rows
+        .iter()
+        .map(|(label, value)| format!("<tr><th>{}</th><td>{}</td></tr>", label, escape_html(value)))
+        .collect();
+
+    let notice = if v.provenance.is_some() {
+        "<p>This badge was imported from an external credential, \
+         which has not been verified by its original issuer.</p>"
+    } else {
+        ""
+    };
+
+    render_page(
+        &v.title,
+        &format!(
+            "<p><strong>{}</strong></p>{}<table>{}</table>",
+            state, notice, rows
+        ),
+    )
+}
+
+/// render_error renders the page shown instead of a badge, explaining why it cannot be shown.
+fn render_error(err: &Error) -> String {
+    let (title, message) = match err {
+        Error::NotFound { .. } => ("Badge not found", "This badge does not exist.".to_string()),
+        Error::Unauthorized { .. } => (
+            "Sign in required",
+            "You have to sign in to view this badge.".to_string(),
+        ),
+        Error::Forbidden { .. } => (
+            "Access denied",
+            "You are not allowed to view this badge.".to_string(),
+        ),
+        Error::InvalidInput { message, .. } => ("Invalid request", message.clone()),
+        Error::Conflict { message } => ("Badge not available", message.clone()),
+        Error::Unavailable { message } => ("Temporarily unavailable", message.clone()),
+    };
+    render_page(title, &format!("<p>{}</p>", escape_html(&message)))
+}
+
+fn render_page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+         <body><h1>{0}</h1>{1}</body></html>",
+        escape_html(title),
+        body
+    )
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn json<T: Serialize>(status_code: u16, value: &T) -> HttpResponse {
+    let body = serde_json::to_vec(value).expect("Could not encode JSON.");
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+        body,
+    }
+}
+
+fn response(status_code: u16, content_type: &str, body: String) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), content_type.to_string())],
+        body: body.into_bytes(),
+    }
+}
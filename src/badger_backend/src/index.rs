@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::time::Duration;
+
+use candid::Principal;
+
+use crate::certification;
+use crate::model::{BadgeSearch, BadgeStatus, StoredAccessRequest, StoredBadge};
+use crate::{
+    BADGES, BADGES_BY_ISSUER, BADGES_BY_OWNER, BADGE_EXPIRIES, REQUESTS_BY_REQUESTER,
+    REQUEST_OWNERS,
+};
+
+/// The number of badges added to the search index and the certified tree per timer tick
+/// while they are rebuilt after an upgrade.
+const REBUILD_BATCH_SIZE: usize = 500;
+
+thread_local! {
+    static BADGE_INDEX: RefCell<BadgeIndex> = RefCell::default();
+
+    /// The ID of the next badge to add while the heap indexes are rebuilt, or `None` once they are complete.
+    static REBUILD_FROM: RefCell<Option<u128>> = const { RefCell::new(None) };
+}
+
+/// BadgeIndex maps the searchable fields of all badges to the IDs of the badges holding them.
+/// The index is derived from `BADGES` and kept on the heap, so it is rebuilt after every upgrade.
+/// The owner, issuer and expiry indexes are kept in stable memory instead and maintained on write.
+#[derive(Default)]
+struct BadgeIndex {
+    /// Lowercase words of the title and description.
+    terms: BTreeMap<String, BTreeSet<u128>>,
+
+    claim_keys: BTreeMap<String, BTreeSet<u128>>,
+
+    claims: BTreeMap<(String, String), BTreeSet<u128>>,
+
+    badge_types: BTreeMap<u16, BTreeSet<u128>>,
+
+    statuses: BTreeMap<BadgeStatus, BTreeSet<u128>>,
+
+    created_at: BTreeSet<(u64, u128)>,
+}
+
+impl BadgeIndex {
+    fn insert(&mut self, badge: &StoredBadge) {
+        for term in terms(badge) {
+            add(&mut self.terms, term, badge.id);
+        }
+        for claim in badge.claims.iter() {
+            add(&mut self.claim_keys, claim.key.clone(), badge.id);
+            add(
+                &mut self.claims,
+                (claim.key.clone(), claim.value.clone()),
+                badge.id,
+            );
+        }
+        add(&mut self.badge_types, badge.badge_type, badge.id);
+        add(&mut self.statuses, badge.status, badge.id);
+        self.created_at.insert((badge.created_at, badge.id));
+    }
+
+    fn remove(&mut self, badge: &StoredBadge) {
+        for term in terms(badge) {
+            remove(&mut self.terms, &term, badge.id);
+        }
+        for claim in badge.claims.iter() {
+            remove(&mut self.claim_keys, &claim.key, badge.id);
+            remove(
+                &mut self.claims,
+                &(claim.key.clone(), claim.value.clone()),
+                badge.id,
+            );
+        }
+        remove(&mut self.badge_types, &badge.badge_type, badge.id);
+        remove(&mut self.statuses, &badge.status, badge.id);
+        self.created_at.remove(&(badge.created_at, badge.id));
+    }
+
+    /// candidates returns the IDs of the badges that may match the search,
+    /// or `None` if the search does not restrict the badges at all.
+    /// Text and status filters only narrow the candidates down, so matches have to be verified
+    /// against the badges themselves.
+    fn candidates(&self, search: &BadgeSearch) -> Option<BTreeSet<u128>> {
+        let mut sets: Vec<BTreeSet<u128>> = Vec::new();
+
+        if let Some(text) = &search.text {
+            for word in text.to_lowercase().split_whitespace() {
+                sets.push(
+                    self.terms
+                        .iter()
+                        .filter(|(term, _)| term.contains(word))
+                        .flat_map(|(_, ids)| ids.iter().copied())
+                        .collect(),
+                );
+            }
+        }
+
+        if let Some(claim) = &search.claim {
+            let ids = match &claim.value {
+                Some(value) => self.claims.get(&(claim.key.clone(), value.clone())),
+                None => self.claim_keys.get(&claim.key),
+            };
+            sets.push(ids.cloned().unwrap_or_default());
+        }
+
+        if let Some(badge_type) = search.badge_type {
+            sets.push(
+                self.badge_types
+                    .get(&badge_type)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+        }
+
+        if let Some(status) = search.status {
+            // Badges past their expiry date may not have been marked as expired yet.
+            let statuses: &[BadgeStatus] = match status {
+                BadgeStatus::Expired => &[
+                    BadgeStatus::Expired,
+                    BadgeStatus::Active,
+                    BadgeStatus::Suspended,
+                ],
+                _ => &[status],
+            };
+            sets.push(
+                statuses
+                    .iter()
+                    .filter_map(|status| self.statuses.get(status))
+                    .flatten()
+                    .copied()
+                    .collect(),
+            );
+        }
+
+        if let Some(issuer_id) = search.issuer_id {
+            sets.push(badges_by_issuer(issuer_id));
+        }
+
+        if search.created_after.is_some() || search.created_before.is_some() {
+            let from = (search.created_after.unwrap_or(0), 0);
+            let to = (search.created_before.unwrap_or(u64::MAX), u128::MAX);
+            if from > to {
+                return Some(BTreeSet::new());
+            }
+            sets.push(
+                self.created_at
+                    .range(from..=to)
+                    .map(|(_, id)| *id)
+                    .collect(),
+            );
+        }
+
+        sets.into_iter()
+            .reduce(|acc, set| acc.intersection(&set).copied().collect())
+    }
+}
+
+/// terms returns the distinct lowercase words of the title and description of the badge.
+fn terms(badge: &StoredBadge) -> BTreeSet<String> {
+    let description = badge.description.as_deref().unwrap_or_default();
+    badge
+        .title
+        .split_whitespace()
+        .chain(description.split_whitespace())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn add<K: Ord>(map: &mut BTreeMap<K, BTreeSet<u128>>, key: K, id: u128) {
+    map.entry(key).or_default().insert(id);
+}
+
+fn remove<K: Ord>(map: &mut BTreeMap<K, BTreeSet<u128>>, key: &K, id: u128) {
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+/// update_badge replaces the previous version of the badge in the index, if any, with the badge.
+pub fn update_badge(previous: Option<&StoredBadge>, badge: &StoredBadge) {
+    BADGE_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(previous) = previous {
+            index.remove(previous);
+        }
+        index.insert(badge);
+    });
+    update_badge_references(previous, badge);
+    update_badge_expiry(previous, badge);
+}
+
+/// update_badge_references indexes the badge by its owner and its issuer in stable memory.
+pub fn update_badge_references(previous: Option<&StoredBadge>, badge: &StoredBadge) {
+    BADGES_BY_OWNER.with(|owners| {
+        let mut owners = owners.borrow_mut();
+        if let Some(previous) = previous {
+            owners.remove(&(previous.owner_principal.into(), badge.id));
+        }
+        owners.insert((badge.owner_principal.into(), badge.id), ());
+    });
+    BADGES_BY_ISSUER.with(|issuers| {
+        let mut issuers = issuers.borrow_mut();
+        if let Some(previous) = previous {
+            issuers.remove(&(previous.issuer_id, badge.id));
+        }
+        issuers.insert((badge.issuer_id, badge.id), ());
+    });
+}
+
+/// update_badge_expiry keeps the badge in the stable expiry index as long as it can still expire.
+/// Unlike the heap indexes the expiry index is maintained on write only and survives upgrades.
+pub fn update_badge_expiry(previous: Option<&StoredBadge>, badge: &StoredBadge) {
+    BADGE_EXPIRIES.with(|expiries| {
+        let mut expiries = expiries.borrow_mut();
+        if let Some(expires_at) = previous.and_then(|it| it.expires_at) {
+            expiries.remove(&(expires_at, badge.id));
+        }
+        if let Some(expires_at) = badge.expires_at {
+            if badge.status.can_transition_to(BadgeStatus::Expired) {
+                expiries.insert((expires_at, badge.id), ());
+            }
+        }
+    });
+}
+
+/// badges_due_to_expire returns the IDs of the badges whose expiry date is at or before the given time.
+pub fn badges_due_to_expire(now: u64) -> Vec<u128> {
+    BADGE_EXPIRIES.with(|expiries| {
+        expiries
+            .borrow()
+            .range((0, 0)..=(now, u128::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// search_candidates returns the IDs of the badges that may match the search,
+/// or `None` if all badges may match.
+pub fn search_candidates(search: &BadgeSearch) -> Option<BTreeSet<u128>> {
+    BADGE_INDEX.with(|index| index.borrow().candidates(search))
+}
+
+/// badges_by_owner returns the IDs of the badges owned by the principal.
+pub fn badges_by_owner(owner: Principal) -> BTreeSet<u128> {
+    BADGES_BY_OWNER.with(|owners| {
+        owners
+            .borrow()
+            .range((owner.into(), 0)..=(owner.into(), u128::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// badges_by_issuer returns the IDs of the badges issued by the organisation.
+pub fn badges_by_issuer(issuer_id: u128) -> BTreeSet<u128> {
+    BADGES_BY_ISSUER.with(|issuers| {
+        issuers
+            .borrow()
+            .range((issuer_id, 0)..=(issuer_id, u128::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+/// update_access_request indexes the access request stored under the badge owner by its requester.
+/// The requester and the owner of a request never change, so updated requests need no removal.
+pub fn update_access_request(owner: Principal, request: &StoredAccessRequest) {
+    if let Ok(requester) = Principal::from_str(&request.principal_id) {
+        REQUESTS_BY_REQUESTER.with(|requesters| {
+            requesters
+                .borrow_mut()
+                .insert((requester.into(), request.id), owner.into())
+        });
+    }
+    REQUEST_OWNERS.with(|owners| owners.borrow_mut().insert(request.id, owner.into()));
+}
+
+/// requests_by_requester returns the IDs of the access requests sent by the principal,
+/// each with the owner of the requested badge the request is stored under.
+pub fn requests_by_requester(requester: Principal) -> Vec<(u128, Principal)> {
+    REQUESTS_BY_REQUESTER.with(|requesters| {
+        requesters
+            .borrow()
+            .range((requester.into(), 0)..=(requester.into(), u128::MAX))
+            .map(|((_, id), owner)| (id, owner.0))
+            .collect()
+    })
+}
+
+/// request_owner returns the owner of the badge the access request is stored under.
+pub fn request_owner(request_id: u128) -> Option<Principal> {
+    REQUEST_OWNERS.with(|owners| owners.borrow().get(&request_id).map(|owner| owner.0))
+}
+
+/// start_rebuild rebuilds the search index and the certified badge tree from scratch.
+/// Badges are added in batches on timer ticks, so that no single message has to load every badge.
+pub fn start_rebuild() {
+    BADGE_INDEX.with(|it| *it.borrow_mut() = BadgeIndex::default());
+    certification::reset();
+    REBUILD_FROM.with(|it| *it.borrow_mut() = Some(0));
+    ic_cdk_timers::set_timer(Duration::ZERO, rebuild_batch);
+}
+
+/// is_rebuilding returns true as long as the search index and the certified tree miss badges.
+pub fn is_rebuilding() -> bool {
+    REBUILD_FROM.with(|it| it.borrow().is_some())
+}
+
+fn rebuild_batch() {
+    let from = REBUILD_FROM.with(|it| *it.borrow());
+    if from.is_none() {
+        return;
+    }
+
+    let batch: Vec<StoredBadge> = BADGES.with(|badges| {
+        badges
+            .borrow()
+            .range(from.unwrap()..)
+            .take(REBUILD_BATCH_SIZE)
+            .map(|(_, badge)| badge)
+            .collect()
+    });
+
+    BADGE_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for badge in batch.iter() {
+            index.insert(badge);
+        }
+    });
+    certification::certify_badges(&batch);
+
+    let next = match batch.last() {
+        Some(last) if batch.len() == REBUILD_BATCH_SIZE => Some(last.id + 1),
+        _ => None,
+    };
+    REBUILD_FROM.with(|it| *it.borrow_mut() = next);
+    if next.is_some() {
+        ic_cdk_timers::set_timer(Duration::ZERO, rebuild_batch);
+    }
+}
@@ -1,51 +1,278 @@
+mod certification;
+mod config;
+mod http;
+mod index;
+mod memory;
+mod migration;
 mod model;
+mod ob;
+mod pagination;
+mod signing;
 mod util;
-
-use crate::model::{Badge, NewBadge, NewUser, Organisation, Response, Role, StableData, User};
+mod vc;
+
+use crate::model::{
+    Badge, BadgeSearch, BadgeSort, BadgeSortBy, BadgeStatus, Error, IdKind, NewBadge,
+    NewOrganisation, NewRevocation, NewUser, Organisation, Page, PageRequest, Response, Revocation,
+    Role, RoleRequest, RoleRequestStatus, SortOrder, StoredBadge, User, UserSort, UserSortBy,
+    UserUpdate,
+};
+use crate::pagination::Cursor;
 use crate::util::{authenticated_caller, authenticated_user};
 use candid::Principal;
 use ic_cdk::api::time;
-use ic_cdk::{init, post_upgrade, pre_upgrade, query, storage, update};
-use model::{AccessRequest, StoredAccessRequest};
+use ic_cdk::{init, post_upgrade, query, update};
+use ic_stable_structures::StableBTreeMap;
+use memory::{
+    Memory, StorablePrincipal, StorableVec, ACCESS_REQUESTS_MEMORY_ID, BADGES_BY_ISSUER_MEMORY_ID,
+    BADGES_BY_OWNER_MEMORY_ID, BADGES_MEMORY_ID, BADGE_ACCESS_APPROVALS_MEMORY_ID,
+    BADGE_EXPIRIES_MEMORY_ID, ID_COUNTERS_MEMORY_ID, ORGANISATIONS_MEMORY_ID,
+    REQUESTS_BY_REQUESTER_MEMORY_ID, REQUEST_OWNERS_MEMORY_ID, ROLES_MEMORY_ID,
+    ROLE_REQUESTS_MEMORY_ID, USERS_MEMORY_ID,
+};
+use model::{
+    AccessGrant, AccessRequest, AccessRequestStatus, BadgeSummary, CertifiedBadgeVerification,
+    HttpRequest, HttpResponse, InitArgs, NewAccessRequest, NewImportedBadge, SentAccessRequest,
+    SigningPublicKey, StoredAccessRequest, StoredUser,
+};
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::str::FromStr;
+use std::time::Duration;
 
 const STUDENT_ROLE_ID: u128 = 1;
 const LECTURER_ROLE_ID: u128 = 2;
 const ADMINISTRATOR_ROLE_ID: u128 = 3;
 const COMPANY_ROLE_ID: u128 = 4;
 
-type UsersMap = BTreeMap<Principal, User>;
-type OrganizationsMap = BTreeMap<u128, Organisation>;
-type BadgesMap = BTreeMap<u128, Badge>;
-type RolesMap = BTreeMap<u128, Role>;
-type AccessRequestsMap = BTreeMap<Principal, Vec<StoredAccessRequest>>;
-type BadgeAccessApprovalsMap = BTreeMap<u128, Vec<Principal>>; // Badge ID -> List of Principals
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const MAX_PURPOSE_LENGTH: usize = 200;
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+type UsersMap = StableBTreeMap<StorablePrincipal, StoredUser, Memory>;
+type OrganizationsMap = StableBTreeMap<u128, Organisation, Memory>;
+type BadgesMap = StableBTreeMap<u128, StoredBadge, Memory>;
+type RolesMap = StableBTreeMap<u128, Role, Memory>;
+type AccessRequestsMap =
+    StableBTreeMap<StorablePrincipal, StorableVec<StoredAccessRequest>, Memory>;
+type BadgeAccessApprovalsMap = StableBTreeMap<u128, StorableVec<AccessGrant>, Memory>; // Badge ID -> List of access grants
+type IdCountersMap = StableBTreeMap<IdKind, u128, Memory>; // Entity kind -> Last allocated ID
+type RoleRequestsMap = StableBTreeMap<StorablePrincipal, RoleRequest, Memory>;
+type BadgeExpiriesMap = StableBTreeMap<(u64, u128), (), Memory>; // (Expiry date, Badge ID) of badges that have yet to expire
+type BadgesByOwnerMap = StableBTreeMap<(StorablePrincipal, u128), (), Memory>; // (Owner, Badge ID)
+type BadgesByIssuerMap = StableBTreeMap<(u128, u128), (), Memory>; // (Organisation ID, Badge ID)
+type RequestsByRequesterMap = StableBTreeMap<(StorablePrincipal, u128), StorablePrincipal, Memory>; // (Requester, Request ID) -> Badge owner
+type RequestOwnersMap = StableBTreeMap<u128, StorablePrincipal, Memory>; // Request ID -> Badge owner
 
 thread_local! {
-    pub static PRINCIPALS: RefCell<UsersMap> = RefCell::default();
-    pub static ORGANISATIONS: RefCell<OrganizationsMap> = RefCell::default();
-    pub static BADGES: RefCell<BadgesMap> = RefCell::default();
-    pub static ROLES: RefCell<RolesMap> = RefCell::default();
-    pub static ACCESS_REQUESTS: RefCell<AccessRequestsMap> = RefCell::default();
-    pub static BADGE_ACCESS_APPROVALS: RefCell<BadgeAccessApprovalsMap> = RefCell::default();
+    pub static PRINCIPALS: RefCell<UsersMap> = RefCell::new(StableBTreeMap::init(memory::get(USERS_MEMORY_ID)));
+    pub static ORGANISATIONS: RefCell<OrganizationsMap> = RefCell::new(StableBTreeMap::init(memory::get(ORGANISATIONS_MEMORY_ID)));
+    pub static BADGES: RefCell<BadgesMap> = RefCell::new(StableBTreeMap::init(memory::get(BADGES_MEMORY_ID)));
+    pub static ROLES: RefCell<RolesMap> = RefCell::new(StableBTreeMap::init(memory::get(ROLES_MEMORY_ID)));
+    pub static ACCESS_REQUESTS: RefCell<AccessRequestsMap> = RefCell::new(StableBTreeMap::init(memory::get(ACCESS_REQUESTS_MEMORY_ID)));
+    pub static BADGE_ACCESS_APPROVALS: RefCell<BadgeAccessApprovalsMap> = RefCell::new(StableBTreeMap::init(memory::get(BADGE_ACCESS_APPROVALS_MEMORY_ID)));
+    pub static ID_COUNTERS: RefCell<IdCountersMap> = RefCell::new(StableBTreeMap::init(memory::get(ID_COUNTERS_MEMORY_ID)));
+    pub static ROLE_REQUESTS: RefCell<RoleRequestsMap> = RefCell::new(StableBTreeMap::init(memory::get(ROLE_REQUESTS_MEMORY_ID)));
+    pub static BADGE_EXPIRIES: RefCell<BadgeExpiriesMap> = RefCell::new(StableBTreeMap::init(memory::get(BADGE_EXPIRIES_MEMORY_ID)));
+    pub static BADGES_BY_OWNER: RefCell<BadgesByOwnerMap> = RefCell::new(StableBTreeMap::init(memory::get(BADGES_BY_OWNER_MEMORY_ID)));
+    pub static BADGES_BY_ISSUER: RefCell<BadgesByIssuerMap> = RefCell::new(StableBTreeMap::init(memory::get(BADGES_BY_ISSUER_MEMORY_ID)));
+    pub static REQUESTS_BY_REQUESTER: RefCell<RequestsByRequesterMap> = RefCell::new(StableBTreeMap::init(memory::get(REQUESTS_BY_REQUESTER_MEMORY_ID)));
+    pub static REQUEST_OWNERS: RefCell<RequestOwnersMap> = RefCell::new(StableBTreeMap::init(memory::get(REQUEST_OWNERS_MEMORY_ID)));
+}
+
+#[query]
+fn organisations_get_all(include_archived: Option<bool>) -> Response<Vec<Organisation>> {
+    if let Err(err) = authenticated_caller() {
+        return Response::Err(err);
+    }
+
+    let include_archived = include_archived.unwrap_or(false);
+
+    ORGANISATIONS.with(|orgs| {
+        let orgs = orgs.borrow();
+        let orgs: Vec<Organisation> = orgs
+            .values()
+            .filter(|org| include_archived || !org.is_archived())
+            .collect();
+        Response::Ok(orgs)
+    })
 }
 
 #[query]
-fn organisations_get_all() -> Response<Vec<Organisation>> {
-    authenticated_caller();
+fn organisations_get_one(organisation_id: u128) -> Response<Organisation> {
+    if let Err(err) = authenticated_caller() {
+        return Response::Err(err);
+    }
+
+    ORGANISATIONS.with(|orgs| match orgs.borrow().get(&organisation_id) {
+        Some(org) => Response::Ok(org),
+        None => Response::Err(Error::not_found("organisation", organisation_id)),
+    })
+}
+
+#[update]
+fn organisations_create_one(organisation: NewOrganisation) -> Response<Organisation> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    if !user.unwrap().is_admin() {
+        return Response::Err(Error::forbidden(
+            p,
+            format!("User with principal {} cannot manage organisations.", p),
+        ));
+    }
+
+    if organisation.name.trim().is_empty() {
+        return Response::Err(Error::invalid_input(
+            "name",
+            "Organisation name must not be empty.",
+        ));
+    }
+
+    let inserted = Organisation {
+        id: util::next_id(IdKind::Organisation),
+        name: organisation.name,
+        description: organisation.description,
+        website: organisation.website,
+        country: organisation.country,
+        logo: organisation.logo,
+        created_at: time(),
+        archived_at: None,
+    };
+
+    ORGANISATIONS.with(|orgs| {
+        let mut orgs = orgs.borrow_mut();
+        orgs.insert(inserted.id, inserted.clone());
+        Response::Ok(inserted)
+    })
+}
+
+#[update]
+fn organisations_update_one(
+    organisation_id: u128,
+    organisation: NewOrganisation,
+) -> Response<Organisation> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
 
-    ORGANISATIONS.with(|orgs| Response::Ok(orgs.borrow().values().cloned().collect()))
+    if !user.unwrap().is_admin() {
+        return Response::Err(Error::forbidden(
+            p,
+            format!("User with principal {} cannot manage organisations.", p),
+        ));
+    }
+
+    if organisation.name.trim().is_empty() {
+        return Response::Err(Error::invalid_input(
+            "name",
+            "Organisation name must not be empty.",
+        ));
+    }
+
+    let existing = ORGANISATIONS.with(|orgs| orgs.borrow().get(&organisation_id));
+
+    if existing.is_none() {
+        return Response::Err(Error::not_found("organisation", organisation_id));
+    }
+
+    let existing = existing.unwrap();
+
+    if existing.is_archived() {
+        return Response::Err(Error::conflict(format!(
+            "Organisation with id {} is archived.",
+            organisation_id
+        )));
+    }
+
+    let updated = Organisation {
+        name: organisation.name,
+        description: organisation.description,
+        website: organisation.website,
+        country: organisation.country,
+        logo: organisation.logo,
+        ..existing
+    };
+
+    ORGANISATIONS.with(|orgs| {
+        let mut orgs = orgs.borrow_mut();
+        orgs.insert(updated.id, updated.clone());
+        Response::Ok(updated)
+    })
+}
+
+#[update]
+fn organisations_archive_one(organisation_id: u128) -> Response<bool> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    if !user.unwrap().is_admin() {
+        return Response::Err(Error::forbidden(
+            p,
+            format!("User with principal {} cannot manage organisations.", p),
+        ));
+    }
+
+    let existing = ORGANISATIONS.with(|orgs| orgs.borrow().get(&organisation_id));
+
+    if existing.is_none() {
+        return Response::Err(Error::not_found("organisation", organisation_id));
+    }
+
+    let mut existing = existing.unwrap();
+
+    if existing.is_archived() {
+        return Response::Err(Error::conflict(format!(
+            "Organisation with id {} is already archived.",
+            organisation_id
+        )));
+    }
+
+    existing.archived_at = Some(time());
+
+    ORGANISATIONS.with(|orgs| {
+        let mut orgs = orgs.borrow_mut();
+        orgs.insert(existing.id, existing);
+        Response::Ok(true)
+    })
 }
 
 #[query]
-fn users_get_all(organisation_id: Option<u128>, role_id: Option<u128>) -> Response<Vec<User>> {
-    let p = authenticated_caller();
-    let auth_user = PRINCIPALS.with(|it| it.borrow().get(&p).cloned());
+fn users_get_all(
+    organisation_id: Option<u128>,
+    role_id: Option<u128>,
+    sort: Option<UserSort>,
+    page: Option<PageRequest>,
+) -> Response<Page<User>> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let auth_user = authenticated_user(p);
 
     if auth_user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let auth_user = auth_user.unwrap();
@@ -74,24 +301,36 @@ fn users_get_all(organisation_id: Option<u128>, role_id: Option<u128>) -> Respon
         org_filter(other_user) && role_filter(other_user)
     }
 
+    let sort = sort.unwrap_or(UserSort {
+        by: UserSortBy::CreatedAt,
+        order: SortOrder::Asc,
+    });
+
     PRINCIPALS.with(|principals| {
         let principals = principals.borrow();
-        let users: Vec<User> = principals
+        let users: Vec<(Cursor, User)> = principals
             .values()
-            .cloned()
+            .map(|user| util::hydrate_user(&user))
             .filter(|user| user_filter(&auth_user, user, organisation_id, role_id))
+            .map(|user| (pagination::user_cursor(&user, sort.by), user))
             .collect();
-        Response::Ok(users)
+        match pagination::paginate(users, sort.order, page) {
+            Ok(page) => Response::Ok(page),
+            Err(err) => Response::Err(err),
+        }
     })
 }
 
 #[query]
 fn users_get_one(principal_id: String) -> Response<User> {
-    let p = authenticated_caller();
-    let auth_user = PRINCIPALS.with(|it| it.borrow().get(&p).cloned());
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let auth_user = authenticated_user(p);
 
     if auth_user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let auth_user = auth_user.unwrap();
@@ -99,275 +338,952 @@ fn users_get_one(principal_id: String) -> Response<User> {
     let principal = Principal::from_str(&principal_id);
 
     if principal.is_err() {
-        return Response::Err(format!("Invalid principal id: {}", principal_id));
+        return Response::Err(Error::invalid_input(
+            "principal_id",
+            format!("Invalid principal id: {}", principal_id),
+        ));
     }
 
     let principal = principal.unwrap();
 
-    PRINCIPALS.with(|principals| {
-        let principals = principals.borrow();
-        match principals.get(&principal) {
-            Some(user) => {
-                if !auth_user.has_user_access(user) {
-                    return Response::Err(format!(
+    match authenticated_user(principal) {
+        Some(user) => {
+            if !auth_user.has_user_access(&user) {
+                return Response::Err(Error::forbidden(
+                    p,
+                    format!(
                         "User with principal {} does not have access to user with principal {}.",
                         p, principal_id
-                    ));
-                }
-                Response::Ok(user.clone())
+                    ),
+                ));
             }
-            None => Response::Err(format!("User with principal {} not found.", principal_id)),
+            Response::Ok(user)
         }
-    })
+        None => Response::Err(Error::not_found("user", principal_id)),
+    }
 }
 
 #[query]
 fn users_whoami() -> Response<User> {
-    let p = authenticated_caller();
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+
+    match authenticated_user(p) {
+        Some(user) => Response::Ok(user),
+        None => Response::Err(Error::unauthorized(p)),
+    }
+}
+
+#[update]
+fn users_create_one(user: NewUser) -> Response<User> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+
+    if PRINCIPALS.with(|principals| principals.borrow().contains_key(&p.into())) {
+        return Response::Err(Error::conflict(format!(
+            "User with principal {} already exists.",
+            p
+        )));
+    }
+
+    let organisation: Option<Organisation> = ORGANISATIONS.with(|orgs| {
+        let orgs = orgs.borrow();
+        orgs.get(&user.organisation_id)
+    });
+
+    if organisation.is_none() {
+        return Response::Err(Error::not_found("organisation", user.organisation_id));
+    }
+
+    let organisation = organisation.unwrap();
+
+    if organisation.is_archived() {
+        return Response::Err(Error::conflict(format!(
+            "Organisation with id {} is archived.",
+            user.organisation_id
+        )));
+    }
+
+    let requested_roles = match util::find_roles(&user.roles) {
+        Ok(roles) => roles,
+        Err(err) => return Response::Err(err),
+    };
+
+    // Privileged roles have to be approved by an administrator before they are granted.
+    // The very first administrator is granted their roles directly, since nobody could approve them otherwise,
+    // but only to the principal named as administrator when the canister was installed or upgraded.
+    let is_first_administrator = config::get().administrator == Some(p)
+        && !PRINCIPALS.with(|principals| principals.borrow().values().any(|it| it.is_admin()))
+        && requested_roles
+            .iter()
+            .any(|role| role.id == ADMINISTRATOR_ROLE_ID);
+
+    let (pending_roles, granted_roles): (Vec<Role>, Vec<Role>) = if is_first_administrator {
+        (Vec::new(), requested_roles)
+    } else {
+        requested_roles
+            .into_iter()
+            .partition(|role| role.is_privileged())
+    };
+
+    let inserted = User {
+        principal_id: p.to_string(),
+        name: user.name.clone(),
+        email: user.email.clone(),
+        organisation,
+        roles: granted_roles,
+        created_at: time(),
+    };
+
+    if !pending_roles.is_empty() {
+        let request = RoleRequest {
+            principal_id: p.to_string(),
+            organisation_id: inserted.organisation.id,
+            roles: pending_roles,
+            status: RoleRequestStatus::Pending,
+            created_at: inserted.created_at,
+            decided_by: None,
+            decided_at: None,
+        };
+        ROLE_REQUESTS.with(|requests| requests.borrow_mut().insert(p.into(), request));
+    }
+
+    util::save_user(&inserted);
+    Response::Ok(inserted)
+}
+
+#[update]
+fn users_update_me(update: UserUpdate) -> Response<User> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    if update.name.trim().is_empty() {
+        return Response::Err(Error::invalid_input("name", "User name must not be empty."));
+    }
+
+    if update.email.trim().is_empty() {
+        return Response::Err(Error::invalid_input(
+            "email",
+            "User email must not be empty.",
+        ));
+    }
+
+    let updated = User {
+        name: update.name,
+        email: update.email,
+        ..user.unwrap()
+    };
+
+    util::save_user(&updated);
+    Response::Ok(updated)
+}
+
+#[update]
+fn users_set_roles(principal_id: String, role_ids: Vec<u128>) -> Response<User> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    let principal = Principal::from_str(&principal_id);
+
+    if principal.is_err() {
+        return Response::Err(Error::invalid_input(
+            "principal_id",
+            format!("Invalid principal id: {}", principal_id),
+        ));
+    }
+
+    let principal = principal.unwrap();
+
+    let other_user = authenticated_user(principal);
+
+    if other_user.is_none() {
+        return Response::Err(Error::not_found("user", principal_id));
+    }
+
+    let other_user = other_user.unwrap();
+
+    if !user.can_manage_user(&other_user) {
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot manage user with principal {}.",
+                p, principal_id
+            ),
+        ));
+    }
+
+    if user.principal_id == other_user.principal_id && !role_ids.contains(&ADMINISTRATOR_ROLE_ID) {
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot remove their own administrator role.",
+                p
+            ),
+        ));
+    }
+
+    let roles = match util::find_roles(&role_ids) {
+        Ok(roles) => roles,
+        Err(err) => return Response::Err(err),
+    };
+
+    // Roles set directly supersede a pending role request, so that a later approval
+    // cannot grant roles the administrator has just removed.
+    ROLE_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        if let Some(mut request) = requests.get(&principal.into()) {
+            if request.status == RoleRequestStatus::Pending {
+                request.status = RoleRequestStatus::Rejected;
+                request.decided_by = Some(p.to_string());
+                request.decided_at = Some(time());
+                requests.insert(principal.into(), request);
+            }
+        }
+    });
+
+    let updated = User {
+        roles,
+        ..other_user
+    };
+
+    util::save_user(&updated);
+    Response::Ok(updated)
+}
+
+#[update]
+fn users_move_organisation(principal_id: String, organisation_id: u128) -> Response<User> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    let principal = Principal::from_str(&principal_id);
+
+    if principal.is_err() {
+        return Response::Err(Error::invalid_input(
+            "principal_id",
+            format!("Invalid principal id: {}", principal_id),
+        ));
+    }
+
+    let principal = principal.unwrap();
+
+    let other_user = authenticated_user(principal);
+
+    if other_user.is_none() {
+        return Response::Err(Error::not_found("user", principal_id));
+    }
+
+    let other_user = other_user.unwrap();
+
+    if !user.can_manage_user(&other_user) {
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot manage user with principal {}.",
+                p, principal_id
+            ),
+        ));
+    }
+
+    let organisation = ORGANISATIONS.with(|orgs| orgs.borrow().get(&organisation_id));
+
+    if organisation.is_none() {
+        return Response::Err(Error::not_found("organisation", organisation_id));
+    }
+
+    let organisation = organisation.unwrap();
+
+    if organisation.is_archived() {
+        return Response::Err(Error::conflict(format!(
+            "Organisation with id {} is archived.",
+            organisation_id
+        )));
+    }
+
+    // Privileged roles only apply to the organisation they were approved for. They are removed
+    // and requested again from the administrators of the new organisation, together with the
+    // roles of a pending role request, which follows the user.
+    let (privileged_roles, roles): (Vec<Role>, Vec<Role>) = other_user
+        .roles
+        .iter()
+        .cloned()
+        .partition(|role| role.is_privileged());
+
+    ROLE_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        let pending = requests
+            .get(&principal.into())
+            .filter(|request| request.status == RoleRequestStatus::Pending);
+        if pending.is_none() && privileged_roles.is_empty() {
+            return;
+        }
+
+        let mut request = pending.unwrap_or(RoleRequest {
+            principal_id: principal_id.clone(),
+            organisation_id,
+            roles: Vec::new(),
+            status: RoleRequestStatus::Pending,
+            created_at: time(),
+            decided_by: None,
+            decided_at: None,
+        });
+        request.organisation_id = organisation_id;
+        for role in privileged_roles {
+            if !request.roles.iter().any(|it| it.id == role.id) {
+                request.roles.push(role);
+            }
+        }
+        requests.insert(principal.into(), request);
+    });
+
+    let updated = User {
+        organisation,
+        roles,
+        ..other_user
+    };
+
+    util::save_user(&updated);
+    Response::Ok(updated)
+}
+
+#[query]
+fn role_requests_get_all() -> Response<Vec<RoleRequest>> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    ROLE_REQUESTS.with(|requests| {
+        let requests = requests.borrow();
+        let requests: Vec<RoleRequest> = requests
+            .values()
+            .filter(|request| {
+                request.principal_id == user.principal_id
+                    || util::can_review_role_request(&user, request)
+            })
+            .collect();
+        Response::Ok(requests)
+    })
+}
+
+#[update]
+fn role_requests_approve_one(principal_id: String) -> Response<RoleRequest> {
+    decide_role_request(principal_id, RoleRequestStatus::Approved)
+}
+
+#[update]
+fn role_requests_reject_one(principal_id: String) -> Response<RoleRequest> {
+    decide_role_request(principal_id, RoleRequestStatus::Rejected)
+}
+
+/// decide_role_request approves or rejects the pending role request of the user with the given principal.
+/// Approved roles are granted to the user immediately.
+fn decide_role_request(principal_id: String, decision: RoleRequestStatus) -> Response<RoleRequest> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    let principal = Principal::from_str(&principal_id);
+
+    if principal.is_err() {
+        return Response::Err(Error::invalid_input(
+            "principal_id",
+            format!("Invalid principal id: {}", principal_id),
+        ));
+    }
+
+    let principal = principal.unwrap();
+
+    let request = ROLE_REQUESTS.with(|requests| requests.borrow().get(&principal.into()));
+
+    if request.is_none() {
+        return Response::Err(Error::not_found("roleRequest", principal_id));
+    }
+
+    let mut request = request.unwrap();
+
+    if !util::can_review_role_request(&user, &request) {
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot review role request for user with principal {}.",
+                p, principal_id
+            ),
+        ));
+    }
+
+    if request.status != RoleRequestStatus::Pending {
+        return Response::Err(Error::conflict(format!(
+            "Role request for user with principal {} has already been decided.",
+            principal_id
+        )));
+    }
+
+    if decision == RoleRequestStatus::Approved {
+        let requester = authenticated_user(principal);
+
+        if requester.is_none() {
+            return Response::Err(Error::not_found("user", principal_id));
+        }
+
+        let mut requester = requester.unwrap();
+        for role in request.roles.iter() {
+            if !requester.has_role(role.id) {
+                requester.roles.push(role.clone());
+            }
+        }
+        util::save_user(&requester);
+    }
+
+    request.status = decision;
+    request.decided_by = Some(p.to_string());
+    request.decided_at = Some(time());
+
+    ROLE_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        requests.insert(principal.into(), request.clone());
+        Response::Ok(request)
+    })
+}
+
+#[query]
+fn badges_get_all(
+    principal_id: Option<String>,
+    organisation_id: Option<u128>,
+    sort: Option<BadgeSort>,
+    page: Option<PageRequest>,
+) -> Response<Page<Badge>> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    // The owner and issuer filters are resolved through the index,
+    // so only the badges matching both are read from stable memory.
+    let owned = principal_id.map(|id| match Principal::from_text(id) {
+        Ok(owner) => index::badges_by_owner(owner),
+        Err(_) => BTreeSet::new(),
+    });
+    let issued = organisation_id.map(index::badges_by_issuer);
+    let ids: Option<BTreeSet<u128>> = match (owned, issued) {
+        (Some(owned), Some(issued)) => Some(owned.intersection(&issued).copied().collect()),
+        (owned, issued) => owned.or(issued),
+    };
+
+    let sort = sort.unwrap_or(BadgeSort {
+        by: BadgeSortBy::CreatedAt,
+        order: SortOrder::Asc,
+    });
+
+    let badges: Vec<(Cursor, StoredBadge)> = BADGES.with(|badges| {
+        let badges = badges.borrow();
+        let found: Vec<StoredBadge> = match ids {
+            Some(ids) => ids.iter().filter_map(|id| badges.get(id)).collect(),
+            None => badges.values().collect(),
+        };
+        found
+            .into_iter()
+            .filter(|badge| user.has_badge_access(badge))
+            .map(|badge| (pagination::badge_cursor(&badge, sort.by), badge))
+            .collect()
+    });
+
+    // Only the badges on the requested page are resolved.
+    match pagination::paginate(badges, sort.order, page) {
+        Ok(page) => Response::Ok(
+            page.map(|b| util::clear_claims(&user, &util::hydrate_badge(&util::with_expiry(&b)))),
+        ),
+        Err(err) => Response::Err(err),
+    }
+}
+
+#[query]
+fn badges_get_one(badge_id: u128) -> Response<Badge> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    BADGES.with(|badges| match badges.borrow().get(&badge_id) {
+        Some(badge) => {
+            if !user.has_badge_access(&badge) {
+                return Response::Err(Error::forbidden(
+                    p,
+                    format!(
+                        "User with principal {} does not have access to badge with id {}.",
+                        p, badge_id
+                    ),
+                ));
+            }
+            Response::Ok(util::clear_claims(
+                &user,
+                &util::hydrate_badge(&util::with_expiry(&badge)),
+            ))
+        }
+        None => Response::Err(Error::not_found("badge", badge_id)),
+    })
+}
+
+/// badges_export_vc returns the badge as a W3C Verifiable Credential in JSON-LD.
+/// It applies the same access checks and claim masking as `badges_get_one`.
+#[query]
+fn badges_export_vc(badge_id: u128) -> Response<String> {
+    match badges_get_one(badge_id) {
+        Response::Ok(badge) => {
+            let proof = credential_proof(&badge);
+            Response::Ok(vc::to_verifiable_credential(&badge, proof).to_string())
+        }
+        Response::Err(err) => Response::Err(err),
+    }
+}
+
+/// credential_proof returns the proof of the canister signature for a badge masked for the caller.
+fn credential_proof(badge: &Badge) -> Option<serde_json::Value> {
+    let stored = BADGES.with(|badges| badges.borrow().get(&badge.id))?;
+    signing::proof(ic_cdk::api::id(), &stored, &badge.claims)
+}
 
-    PRINCIPALS.with(|principals| {
-        let principals = principals.borrow();
-        match principals.get(&p) {
-            Some(user) => Response::Ok(user.clone()),
-            None => Response::Err(format!("User with principal {} not found.", p)),
+/// badges_export_ob returns the badge as an Open Badges 3.0 credential in JSON-LD.
+/// It applies the same access checks and claim masking as `badges_get_one`.
+#[query]
+fn badges_export_ob(badge_id: u128) -> Response<String> {
+    match badges_get_one(badge_id) {
+        Response::Ok(badge) => {
+            let proof = credential_proof(&badge);
+            Response::Ok(ob::to_open_badge(&badge, proof).to_string())
         }
-    })
+        Response::Err(err) => Response::Err(err),
+    }
 }
 
+/// badges_import_ob creates a badge from an externally issued Open Badges 3.0 credential.
+/// The badge is held by the organisation of the importing administrator,
+/// while its provenance records the original issuer.
 #[update]
-fn users_create_one(user: NewUser) -> Response<User> {
-    let p = authenticated_caller();
+fn badges_import_ob(badge: NewImportedBadge) -> Response<Badge> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
 
-    if let Some(_) = PRINCIPALS.with(|principals| {
-        let principals = principals.borrow();
-        principals.get(&p).cloned()
-    }) {
-        return Response::Err(format!("User with principal {} already exists.", p));
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
     }
 
-    let organisation: Option<Organisation> = ORGANISATIONS.with(|orgs| {
+    let user = user.unwrap();
+
+    if !user.is_admin() {
+        return Response::Err(Error::forbidden(
+            p,
+            format!("User with principal {} cannot import badges.", p),
+        ));
+    }
+
+    let organisation = ORGANISATIONS.with(|orgs| {
         let orgs = orgs.borrow();
-        orgs.get(&user.organisation_id).cloned()
+        orgs.get(&user.organisation.id)
     });
 
     if organisation.is_none() {
-        return Response::Err(format!(
-            "Organisation with id {} not found.",
-            user.organisation_id
-        ));
+        return Response::Err(Error::not_found("organisation", user.organisation.id));
     }
 
-    let roles_result: Result<Vec<Role>, String> = ROLES.with(|roles_map| {
-        let roles_map = roles_map.borrow();
-        let mut roles = Vec::new();
-        for role_id in user.roles {
-            match roles_map.get(&role_id) {
-                Some(role) => roles.push(role.clone()),
-                None => return Err(format!("Role with id {} not found.", role_id)),
-            }
-        }
-        Ok(roles)
-    });
+    let organisation = organisation.unwrap();
 
-    if roles_result.is_err() {
-        return Response::Err(roles_result.unwrap_err());
+    if organisation.is_archived() {
+        return Response::Err(Error::conflict(format!(
+            "Organisation with id {} is archived.",
+            organisation.id
+        )));
     }
 
-    let inserted = User {
-        principal_id: p.to_string(),
-        name: user.name.clone(),
-        email: user.email.clone(),
-        organisation: organisation.unwrap(),
-        roles: roles_result.unwrap(),
-        created_at: time(),
-    };
+    let owner = Principal::from_str(&badge.owner_id);
 
-    PRINCIPALS.with(|principals| {
-        let mut principals = principals.borrow_mut();
-        principals.insert(p, inserted.clone());
-        Response::Ok(inserted)
-    })
-}
+    if owner.is_err() {
+        return Response::Err(Error::invalid_input(
+            "ownerID",
+            format!("Invalid principal id: {}", badge.owner_id),
+        ));
+    }
 
-#[query]
-fn badges_get_all(
-    principal_id: Option<String>,
-    organisation_id: Option<u128>,
-) -> Response<Vec<Badge>> {
-    let p = authenticated_caller();
-    let user = authenticated_user(p);
+    let owner = owner.unwrap();
 
-    if user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+    if authenticated_user(owner).is_none() {
+        return Response::Err(Error::not_found("user", owner));
     }
 
-    let user = user.unwrap();
+    let imported = ob::from_open_badge(&badge.credential, p.to_string(), time());
 
-    fn f(
-        user: &User,
-        badge: &Badge,
-        principal_id: Option<String>,
-        organisation_id: Option<u128>,
-    ) -> bool {
-        if !user.has_badge_access(&badge) {
-            return false;
+    if let Err(err) = imported {
+        return Response::Err(err);
+    }
+
+    let imported = imported.unwrap();
+
+    if let Some(credential_id) = &imported.provenance.credential_id {
+        let duplicate = index::badges_by_owner(owner).iter().any(|id| {
+            let existing = BADGES.with(|badges| badges.borrow().get(id));
+            matches!(existing.and_then(|b| b.provenance), Some(it) if it.credential_id.as_ref() == Some(credential_id))
+        });
+        if duplicate {
+            return Response::Err(Error::conflict(format!(
+                "Credential {} has already been imported for user with principal {}.",
+                credential_id, owner
+            )));
         }
+    }
 
-        let org_filter = |badge: &Badge| match organisation_id {
-            Some(org_id) => badge.issuer.id == org_id,
-            None => true,
-        };
+    let new_badge = StoredBadge {
+        id: util::next_id(IdKind::Badge),
+        title: imported.title,
+        description: imported.description,
+        badge_type: badge.badge_type,
+        issuer_id: organisation.id,
+        owner_principal: owner,
+        status: BadgeStatus::Active,
+        revocation: None,
+        claims: imported.claims,
+        signed_by: Vec::new(),
+        created_at: imported.issued_at,
+        expires_at: imported.expires_at,
+        provenance: Some(imported.provenance),
+        signature: None,
+    };
+    util::save_badge(new_badge.clone());
+    Response::Ok(util::hydrate_badge(&util::with_expiry(&new_badge)))
+}
 
-        let principal_filter = |badge: &Badge| match principal_id {
-            Some(principal_id) => badge.owner.principal_id == principal_id,
-            None => true,
-        };
+/// signing_public_key returns the public key that the signatures in exported credentials are
+/// checked against. It is loaded asynchronously after install and upgrade.
+#[query]
+fn signing_public_key() -> Response<SigningPublicKey> {
+    match signing::public_key() {
+        Some(key) => Response::Ok(key),
+        None => Response::Err(Error::unavailable(
+            "The signing public key has not been loaded yet.",
+        )),
+    }
+}
 
-        org_filter(badge) && principal_filter(badge)
+/// badges_verify can be called by anyone, including anonymous callers,
+/// so that a badge can be verified without registering.
+#[query]
+fn badges_verify(badge_id: u128) -> Response<CertifiedBadgeVerification> {
+    match util::verify_badge(badge_id) {
+        Ok(verification) => Response::Ok(verification),
+        Err(err) => Response::Err(err),
     }
+}
 
-    BADGES.with(|badges| {
-        let badges = badges.borrow();
-        let badges: Vec<Badge> = badges
-            .values()
-            .cloned()
-            .filter(|badge| f(&user, badge, principal_id.clone(), organisation_id))
-            .map(|b| util::clear_claims(&user, &b))
-            .collect();
-        Response::Ok(badges)
-    })
+/// http_request serves the public verification page of a badge to browsers through the HTTP gateway.
+#[query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    http::handle(request)
 }
 
 #[query]
-fn badges_get_one(badge_id: u128) -> Response<Badge> {
-    let p = authenticated_caller();
+fn badges_search(
+    search: BadgeSearch,
+    sort: Option<BadgeSort>,
+    page: Option<PageRequest>,
+) -> Response<Page<Badge>> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
     let user = authenticated_user(p);
 
     if user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let user = user.unwrap();
 
-    BADGES.with(|badges| match badges.borrow().get(&badge_id) {
-        Some(badge) => {
-            if !user.has_badge_access(badge) {
-                return Response::Err(format!(
-                    "User with principal {} does not have access to badge with id {}.",
-                    p, badge_id
-                ));
-            }
-            Response::Ok(util::clear_claims(&user, badge))
+    let sort = sort.unwrap_or(BadgeSort {
+        by: BadgeSortBy::CreatedAt,
+        order: SortOrder::Asc,
+    });
+
+    // Companies can only search by the claims they have been granted access to.
+    let claim_visible = |badge: &StoredBadge| match &search.claim {
+        Some(filter) if user.is_company() => {
+            matches!(util::find_claim_grant(badge.id, &user.principal_id), Some(grant) if grant.allows(&filter.key))
         }
-        None => Response::Err(format!("Badge with id {} not found.", badge_id)),
+        _ => true,
+    };
+
+    if index::is_rebuilding() {
+        return Response::Err(Error::unavailable(
+            "The search index is being rebuilt, please try again shortly.",
+        ));
+    }
+
+    let candidates = index::search_candidates(&search);
+
+    let badges: Vec<(Cursor, StoredBadge)> = BADGES.with(|badges| {
+        let badges = badges.borrow();
+        let found: Vec<StoredBadge> = match candidates {
+            Some(ids) => ids.iter().filter_map(|id| badges.get(id)).collect(),
+            None => badges.values().collect(),
+        };
+        found
+            .iter()
+            .map(util::with_expiry)
+            .filter(|badge| user.has_badge_access(badge) && search.matches(badge))
+            .filter(|badge| claim_visible(badge))
+            .map(|badge| (pagination::badge_cursor(&badge, sort.by), badge))
+            .collect()
+    });
+
+    match pagination::paginate(badges, sort.order, page) {
+        Ok(page) => Response::Ok(page.map(|b| util::clear_claims(&user, &util::hydrate_badge(&b)))),
+        Err(err) => Response::Err(err),
+    }
+}
+
+#[update]
+fn badges_revoke_one(badge_id: u128, revocation: NewRevocation) -> Response<bool> {
+    change_badge_status(badge_id, BadgeStatus::Revoked, "revoke", |badge, p| {
+        badge.revocation = Some(Revocation {
+            reason: revocation.reason,
+            details: revocation.details,
+            revoked_by: p.to_string(),
+            revoked_at: time(),
+        });
     })
 }
 
 #[update]
-fn badges_revoke_one(badge_id: u128) -> Response<bool> {
-    let p = authenticated_caller();
+fn badges_suspend_one(badge_id: u128) -> Response<bool> {
+    change_badge_status(badge_id, BadgeStatus::Suspended, "suspend", |_, _| {})
+}
+
+#[update]
+fn badges_reinstate_one(badge_id: u128) -> Response<bool> {
+    change_badge_status(badge_id, BadgeStatus::Active, "reinstate", |_, _| {})
+}
+
+/// change_badge_status moves the badge to the next status on behalf of the caller.
+/// The `on_transition` callback is invoked after a successful transition to record any details.
+fn change_badge_status(
+    badge_id: u128,
+    next: BadgeStatus,
+    action: &str,
+    on_transition: impl FnOnce(&mut StoredBadge, Principal),
+) -> Response<bool> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
     let user = authenticated_user(p);
 
     if user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let user = user.unwrap();
 
-    BADGES.with(|badges| match badges.borrow_mut().get_mut(&badge_id) {
-        Some(badge) => {
-            if !user.has_badge_access(badge) {
-                return Response::Err(format!(
-                    "User with principal {} does not have access to badge with id {}.",
-                    p, badge_id
+    let badge = BADGES.with(|badges| badges.borrow().get(&badge_id));
+
+    match badge {
+        Some(mut badge) => {
+            if !user.has_badge_access(&badge) {
+                return Response::Err(Error::forbidden(
+                    p,
+                    format!(
+                        "User with principal {} does not have access to badge with id {}.",
+                        p, badge_id
+                    ),
                 ));
             }
-            // A badge can be revoked by an admin or a lecturer.
-            // Furthermore, the lecturer has to be from the same organisation as the issuer.
-            if !user.is_admin() && !user.is_lecturer() || user.organisation.id != badge.issuer.id {
-                return Response::Err(format!(
-                    "User with principal {} cannot revoke badge with id {}.",
-                    p, badge_id
+            if !user.can_change_status(&badge) {
+                return Response::Err(Error::forbidden(
+                    p,
+                    format!(
+                        "User with principal {} cannot {} badge with id {}.",
+                        p, action, badge_id
+                    ),
                 ));
             }
-            badge.is_revoked = true;
+            // The expiry job may not have processed the badge yet.
+            if badge.has_expired(time()) {
+                badge.status = BadgeStatus::Expired;
+            }
+            if let Err(err) = badge.transition_to(next) {
+                return Response::Err(err);
+            }
+            on_transition(&mut badge, p);
+            util::save_badge(badge);
             Response::Ok(true)
         }
-        None => Response::Err(format!("Badge with id {} not found.", badge_id)),
-    })
+        None => Response::Err(Error::not_found("badge", badge_id)),
+    }
 }
 
 #[update]
-fn badges_create_one(badge: NewBadge) -> Response<Badge> {
-    let p = authenticated_caller();
+async fn badges_create_one(badge: NewBadge) -> Response<Badge> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
     let user = authenticated_user(p);
 
     if user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let user = user.unwrap();
 
     if !user.can_create_or_revoke(&badge) {
-        return Response::Err(format!(
-            "User with principal {} cannot issue badge for organisation with id {}.",
-            p, badge.issuer_id
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot issue badge for organisation with id {}.",
+                p, badge.issuer_id
+            ),
         ));
     }
 
+    if let Some(expires_at) = badge.expires_at {
+        if expires_at <= time() {
+            return Response::Err(Error::invalid_input(
+                "expiresAt",
+                format!("Badge expiry date {} must be in the future.", expires_at),
+            ));
+        }
+    }
+
     let organisation = ORGANISATIONS.with(|orgs| {
         let orgs = orgs.borrow();
-        orgs.get(&badge.issuer_id).cloned()
+        orgs.get(&badge.issuer_id)
     });
 
     if organisation.is_none() {
-        return Response::Err(format!(
-            "Organisation with id {} not found.",
+        return Response::Err(Error::not_found("organisation", badge.issuer_id));
+    }
+
+    let organisation = organisation.unwrap();
+
+    if organisation.is_archived() {
+        return Response::Err(Error::conflict(format!(
+            "Organisation with id {} is archived.",
             badge.issuer_id
-        ));
+        )));
     }
 
     let owner = Principal::from_str(&badge.owner_id);
 
     if owner.is_err() {
-        return Response::Err(format!("Invalid principal id: {}", badge.owner_id));
+        return Response::Err(Error::invalid_input(
+            "ownerID",
+            format!("Invalid principal id: {}", badge.owner_id),
+        ));
     }
 
-    let owner = PRINCIPALS.with(|principals| {
-        let principals = principals.borrow();
-        principals.get(&owner.unwrap()).cloned()
-    });
+    let owner = owner.unwrap();
 
-    BADGES.with(|badges| {
-        let mut badges = badges.borrow_mut();
-
-        let new_badge = Badge {
-            id: badges.len() as u128 + 1,
-            title: badge.title,
-            description: badge.description,
-            badge_type: badge.badge_type,
-            issuer: organisation.unwrap(),
-            owner: owner.unwrap(),
-            is_revoked: false,
-            claims: badge.claims,
-            signed_by: vec![p.to_string()],
-            created_at: time(),
-        };
-        badges.insert(new_badge.id, new_badge.clone());
-        Response::Ok(new_badge)
-    })
+    if authenticated_user(owner).is_none() {
+        return Response::Err(Error::not_found("user", owner));
+    }
+
+    let mut new_badge = StoredBadge {
+        id: util::next_id(IdKind::Badge),
+        title: badge.title,
+        description: badge.description,
+        badge_type: badge.badge_type,
+        issuer_id: organisation.id,
+        owner_principal: owner,
+        status: BadgeStatus::Active,
+        revocation: None,
+        claims: badge.claims,
+        signed_by: vec![p.to_string()],
+        created_at: time(),
+        expires_at: badge.expires_at,
+        provenance: None,
+        signature: None,
+    };
+
+    // The badge is only stored once it is signed, so no unsigned badge is ever issued.
+    match signing::sign_badge(&new_badge).await {
+        Ok(signature) => new_badge.signature = Some(signature),
+        Err(err) => return Response::Err(err),
+    }
+
+    util::save_badge(new_badge.clone());
+    Response::Ok(util::hydrate_badge(&new_badge))
 }
 
 #[query]
 fn requests_get_all() -> Response<Vec<AccessRequest>> {
-    let p = authenticated_caller();
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
 
     ACCESS_REQUESTS.with(|requests| {
-        let stored = requests.borrow().get(&p).cloned();
+        let stored = requests.borrow().get(&p.into());
         if stored.is_none() {
             return Response::Ok(Vec::new());
         }
-        let stored = stored.unwrap();
+        let stored = stored.unwrap().0;
 
         let mut requests = Vec::new();
         for request in stored {
@@ -376,28 +1292,31 @@ fn requests_get_all() -> Response<Vec<AccessRequest>> {
                 continue;
             }
             let requesting_principal = requesting_principal.unwrap();
-            let user = PRINCIPALS.with(|principals| {
-                let principals = principals.borrow();
-                principals.get(&requesting_principal).cloned()
-            });
+            let user = authenticated_user(requesting_principal);
             if user.is_none() {
                 continue;
             }
             let user = user.unwrap();
             let badge = BADGES.with(|badges| {
                 let badges = badges.borrow();
-                badges.get(&request.badge_id).cloned()
+                badges.get(&request.badge_id)
             });
             if badge.is_none() {
                 continue;
             }
-            let badge = badge.unwrap();
+            let badge = util::hydrate_badge(&badge.unwrap());
 
             requests.push(AccessRequest {
                 id: request.id,
                 user,
                 badge,
+                claim_keys: request.claim_keys,
+                purpose: request.purpose,
+                message: request.message,
+                legal_basis: request.legal_basis,
+                status: request.status,
                 created_at: request.created_at,
+                decided_at: request.decided_at,
             })
         }
 
@@ -405,244 +1324,521 @@ fn requests_get_all() -> Response<Vec<AccessRequest>> {
     })
 }
 
+#[query]
+fn requests_get_sent() -> Response<Vec<SentAccessRequest>> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let sent: Vec<StoredAccessRequest> = index::requests_by_requester(p)
+        .into_iter()
+        .filter_map(|(request_id, _)| util::find_access_request(request_id))
+        .map(|(_, request)| request)
+        .collect();
+
+    let mut requests = Vec::new();
+    for request in sent {
+        let badge = BADGES.with(|badges| {
+            let badges = badges.borrow();
+            badges.get(&request.badge_id)
+        });
+        if badge.is_none() {
+            continue;
+        }
+        let badge = util::hydrate_badge(&util::with_expiry(&badge.unwrap()));
+
+        requests.push(SentAccessRequest {
+            id: request.id,
+            badge: BadgeSummary::from(&badge),
+            claim_keys: request.claim_keys,
+            purpose: request.purpose,
+            legal_basis: request.legal_basis,
+            status: request.status,
+            created_at: request.created_at,
+            decided_at: request.decided_at,
+        })
+    }
+    requests.sort_by_key(|request| request.id);
+
+    Response::Ok(requests)
+}
+
 #[update]
-fn requests_create_one(badge_id: u128) -> Response<AccessRequest> {
-    let p = authenticated_caller();
+fn requests_create_one(request: NewAccessRequest) -> Response<AccessRequest> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
     let user = authenticated_user(p);
 
     if user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let user = user.unwrap();
 
+    let NewAccessRequest {
+        badge_id,
+        claim_keys,
+        purpose,
+        message,
+        legal_basis,
+    } = request;
+
+    if purpose.trim().is_empty() {
+        return Response::Err(Error::invalid_input(
+            "purpose",
+            "Access request purpose must not be empty.",
+        ));
+    }
+
+    if purpose.chars().count() > MAX_PURPOSE_LENGTH {
+        return Response::Err(Error::invalid_input(
+            "purpose",
+            format!(
+                "Access request purpose must not be longer than {} characters.",
+                MAX_PURPOSE_LENGTH
+            ),
+        ));
+    }
+
+    if matches!(&message, Some(message) if message.chars().count() > MAX_MESSAGE_LENGTH) {
+        return Response::Err(Error::invalid_input(
+            "message",
+            format!(
+                "Access request message must not be longer than {} characters.",
+                MAX_MESSAGE_LENGTH
+            ),
+        ));
+    }
+
     let badge = BADGES.with(|badges| {
         let badges = badges.borrow();
-        badges.get(&badge_id).cloned()
+        badges.get(&badge_id)
     });
 
     if badge.is_none() {
-        return Response::Err(format!("Badge with id {} not found.", badge_id));
+        return Response::Err(Error::not_found("badge", badge_id));
     }
 
     let badge = badge.unwrap();
 
+    if badge.has_expired(time()) {
+        return Response::Err(Error::conflict(format!(
+            "Badge with id {} has expired.",
+            badge_id
+        )));
+    }
+
     if !user.has_badge_access(&badge) {
-        return Response::Err(format!(
-            "User with principal {} does not have access to badge with id {}.",
-            p, badge_id
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} does not have access to badge with id {}.",
+                p, badge_id
+            ),
         ));
     }
 
     if !user.is_company() {
-        return Response::Err(format!(
-            "User with principal {} cannot request access to badge with id {}.",
-            p, badge_id
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot request access to badge with id {}.",
+                p, badge_id
+            ),
         ));
     }
 
-    let owning_principal = Principal::from_str(&badge.owner.principal_id);
+    let owning_principal = badge.owner_principal;
 
-    if owning_principal.is_err() {
-        return Response::Err(format!(
-            "Invalid principal id: {}",
-            badge.owner.principal_id
+    if claim_keys.is_empty() {
+        return Response::Err(Error::invalid_input(
+            "claimKeys",
+            "At least one claim key has to be requested.",
         ));
     }
-    let owning_principal = owning_principal.unwrap();
 
-    let already_approved = BADGE_ACCESS_APPROVALS.with(|approvals| {
-        let approvals = approvals.borrow();
-        let approved_for_badge = approvals.get(&badge_id);
-        if approved_for_badge.is_none() {
-            return false;
-        }
-        let approved_for_badge = approved_for_badge.unwrap();
-        let requeting_principal = Principal::from_str(&user.principal_id).unwrap();
-        approved_for_badge.contains(&requeting_principal)
-    });
+    if let Some(key) = claim_keys.iter().find(|key| !badge.has_claim(key)) {
+        return Response::Err(Error::invalid_input(
+            "claimKeys",
+            format!("Badge with id {} has no claim with key {}.", badge_id, key),
+        ));
+    }
 
-    if already_approved {
-        return Response::Err(format!(
+    let grant = util::find_claim_grant(badge_id, &user.principal_id);
+
+    if grant.is_some()
+        && claim_keys
+            .iter()
+            .all(|key| grant.as_ref().unwrap().allows(key))
+    {
+        return Response::Err(Error::conflict(format!(
             "User with principal {} already has access to badge with id {}.",
             p, badge_id
-        ));
+        )));
     }
 
     ACCESS_REQUESTS.with(|requests| {
         let mut requests = requests.borrow_mut();
+        let mut owning_principal_requests =
+            requests.get(&owning_principal.into()).unwrap_or_default().0;
+        if owning_principal_requests.iter().any(|r| {
+            r.badge_id == badge_id
+                && r.principal_id == p.to_string()
+                && r.status == AccessRequestStatus::Pending
+        }) {
+            return Response::Err(Error::conflict(format!(
+                "User with principal {} already requested access to badge with id {}.",
+                p, badge_id
+            )));
+        }
+
         let to_store = StoredAccessRequest {
-            id: requests.len() as u128 + 1,
+            id: util::next_id(IdKind::AccessRequest),
             principal_id: p.to_string(),
             badge_id,
+            claim_keys: Some(claim_keys),
+            purpose: Some(purpose),
+            message,
+            legal_basis: Some(legal_basis),
+            status: AccessRequestStatus::Pending,
             created_at: time(),
+            decided_at: None,
         };
-
-        if requests.contains_key(&owning_principal) {
-            let owning_principal_requests = requests.get_mut(&owning_principal).unwrap();
-            if owning_principal_requests
-                .iter()
-                .any(|r| r.badge_id == to_store.badge_id)
-            {
-                return Response::Err(format!(
-                    "User with principal {} already requested access to badge with id {}.",
-                    p, badge_id
-                ));
-            }
-            owning_principal_requests.push(to_store.clone());
-        } else {
-            requests.insert(owning_principal, vec![to_store.clone()]);
-        }
+        owning_principal_requests.push(to_store.clone());
+        requests.insert(
+            owning_principal.into(),
+            StorableVec(owning_principal_requests),
+        );
+        index::update_access_request(owning_principal, &to_store);
         Response::Ok(AccessRequest {
             id: to_store.id,
+            badge: util::clear_claims(&user, &util::hydrate_badge(&badge)),
             user,
-            badge,
+            claim_keys: to_store.claim_keys,
+            purpose: to_store.purpose,
+            message: to_store.message,
+            legal_basis: to_store.legal_basis,
+            status: to_store.status,
             created_at: to_store.created_at,
+            decided_at: to_store.decided_at,
         })
     })
 }
 
 #[update]
-fn requests_approve_one(request_id: u128) -> Response<bool> {
-    let p = authenticated_caller();
+fn requests_approve_one(
+    request_id: u128,
+    expires_at: Option<u64>,
+    claim_keys: Option<Vec<String>>,
+) -> Response<bool> {
+    decide_access_request(
+        request_id,
+        AccessRequestStatus::Approved,
+        "approve",
+        expires_at,
+        claim_keys,
+    )
+}
+
+#[update]
+fn requests_deny_one(request_id: u128) -> Response<bool> {
+    decide_access_request(request_id, AccessRequestStatus::Denied, "deny", None, None)
+}
+
+#[update]
+fn requests_withdraw_one(request_id: u128) -> Response<bool> {
+    decide_access_request(
+        request_id,
+        AccessRequestStatus::Withdrawn,
+        "withdraw",
+        None,
+        None,
+    )
+}
+
+/// decide_access_request moves a pending access request to the given status on behalf of the caller.
+/// Requests are approved or denied by the owner of the badge and withdrawn by the requesting company.
+/// An approval grants access to the claims of the badge until `grant_expires_at`, or indefinitely if it is not set.
+/// Only the claims in `grant_claim_keys` are shared, or all requested claims if it is not set.
+/// The grant replaces any earlier grant of the requesting company for the badge.
+fn decide_access_request(
+    request_id: u128,
+    decision: AccessRequestStatus,
+    action: &str,
+    grant_expires_at: Option<u64>,
+    grant_claim_keys: Option<Vec<String>>,
+) -> Response<bool> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
     let user = authenticated_user(p);
 
     if user.is_none() {
-        return Response::Err(format!("User with principal {} not found.", p));
+        return Response::Err(Error::unauthorized(p));
     }
 
     let user = user.unwrap();
 
-    if !user.is_student() {
-        return Response::Err(format!(
-            "User with principal {} cannot approve access requests.",
-            p
-        ));
+    let found = util::find_access_request(request_id);
+
+    if found.is_none() {
+        return Response::Err(Error::not_found("accessRequest", request_id));
     }
 
-    let pending_request = ACCESS_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        let mut pending_request: Option<StoredAccessRequest> = None;
-        for (_, stored) in requests.iter_mut() {
-            let index = stored.iter().position(|r| r.id == request_id);
-            if index.is_some() {
-                pending_request = Some(stored.remove(index.unwrap()));
-                break;
+    let (owning_principal, mut request) = found.unwrap();
+
+    if decision == AccessRequestStatus::Withdrawn {
+        if user.principal_id != request.principal_id {
+            return Response::Err(Error::forbidden(
+                p,
+                format!(
+                    "User with principal {} cannot withdraw access request with id {}.",
+                    p, request_id
+                ),
+            ));
+        }
+    } else {
+        if !user.is_student() {
+            return Response::Err(Error::forbidden(
+                p,
+                format!(
+                    "User with principal {} cannot {} access requests.",
+                    p, action
+                ),
+            ));
+        }
+
+        if user.principal_id != owning_principal.to_text() {
+            return Response::Err(Error::forbidden(
+                p,
+                format!(
+                    "User with principal {} cannot {} access request for badge with id {}.",
+                    p, action, request.badge_id
+                ),
+            ));
+        }
+    }
+
+    if request.status != AccessRequestStatus::Pending {
+        return Response::Err(Error::conflict(format!(
+            "Access request with id {} has already been decided.",
+            request_id
+        )));
+    }
+
+    if decision == AccessRequestStatus::Approved {
+        let requested_badge = BADGES.with(|badges| {
+            let badges = badges.borrow();
+            badges.get(&request.badge_id)
+        });
+        if requested_badge.is_none() {
+            return Response::Err(Error::not_found("badge", request.badge_id));
+        }
+        let requested_badge = requested_badge.unwrap();
+
+        if requested_badge.has_expired(time()) {
+            return Response::Err(Error::conflict(format!(
+                "Badge with id {} has expired.",
+                request.badge_id
+            )));
+        }
+
+        if let Some(expires_at) = grant_expires_at {
+            if expires_at <= time() {
+                return Response::Err(Error::invalid_input(
+                    "expiresAt",
+                    format!("Access expiry date {} must be in the future.", expires_at),
+                ));
             }
         }
-        if pending_request.is_none() {
-            return Result::Err(format!("Access request with id {} not found.", request_id));
+
+        if let Some(keys) = &grant_claim_keys {
+            if keys.is_empty() {
+                return Response::Err(Error::invalid_input(
+                    "claimKeys",
+                    "At least one claim key has to be shared.",
+                ));
+            }
+
+            if let Some(key) = keys.iter().find(|key| !request.includes_claim(key)) {
+                return Response::Err(Error::invalid_input(
+                    "claimKeys",
+                    format!(
+                        "Access request with id {} does not include claim with key {}.",
+                        request_id, key
+                    ),
+                ));
+            }
         }
-        Result::Ok(pending_request.unwrap())
-    });
 
-    if pending_request.is_err() {
-        return Response::Err(pending_request.unwrap_err());
+        let mut claim_keys = grant_claim_keys.or_else(|| request.claim_keys.clone());
+
+        BADGE_ACCESS_APPROVALS.with(|approvals| {
+            let mut approvals = approvals.borrow_mut();
+            let mut approved_for_badge = approvals.get(&request.badge_id).unwrap_or_default();
+            // A new approval replaces an expired grant of the same principal and extends a valid one
+            // with the newly shared claims. The expiry date of the latest approval applies to all of them.
+            let previous = approved_for_badge
+                .0
+                .iter()
+                .position(|grant| grant.principal_id == request.principal_id)
+                .map(|index| approved_for_badge.0.remove(index));
+            if let Some(previous) = previous.filter(|grant| grant.is_valid(time())) {
+                claim_keys = match (previous.claim_keys, claim_keys) {
+                    (Some(mut keys), Some(added)) => {
+                        for key in added {
+                            if !keys.contains(&key) {
+                                keys.push(key);
+                            }
+                        }
+                        Some(keys)
+                    }
+                    _ => None,
+                };
+            }
+            approved_for_badge.0.push(AccessGrant {
+                principal_id: request.principal_id.clone(),
+                granted_at: time(),
+                expires_at: grant_expires_at,
+                claim_keys,
+            });
+            approvals.insert(request.badge_id, approved_for_badge);
+        });
     }
 
-    let pending_request = pending_request.unwrap();
+    request.status = decision;
+    request.decided_at = Some(time());
+    util::save_access_request(owning_principal, request);
+    Response::Ok(true)
+}
 
-    let requested_badge = BADGES.with(|badges| {
-        let badges = badges.borrow();
-        badges.get(&pending_request.badge_id).cloned()
-    });
-    if requested_badge.is_none() {
-        return Response::Err(format!(
-            "Badge with id {} not found.",
-            pending_request.badge_id
-        ));
+#[query]
+fn approvals_get_all(badge_id: u128) -> Response<Vec<AccessGrant>> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    let badge = BADGES.with(|badges| badges.borrow().get(&badge_id));
+
+    if badge.is_none() {
+        return Response::Err(Error::not_found("badge", badge_id));
     }
-    let requested_badge = requested_badge.unwrap();
 
-    if user.principal_id != requested_badge.owner.principal_id {
-        return Response::Err(format!(
-            "User with principal {} cannot approve access request for badge with id {}.",
-            p, pending_request.badge_id
+    if user.principal_id != badge.unwrap().owner_principal.to_text() {
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot view access grants for badge with id {}.",
+                p, badge_id
+            ),
         ));
     }
 
-    let requesting_principal = Principal::from_str(&pending_request.principal_id);
-    if requesting_principal.is_err() {
-        return Response::Err(format!(
-            "Invalid principal id: {}",
-            pending_request.principal_id
+    let now = time();
+    BADGE_ACCESS_APPROVALS.with(|approvals| {
+        let grants = approvals
+            .borrow()
+            .get(&badge_id)
+            .unwrap_or_default()
+            .0
+            .into_iter()
+            .filter(|grant| grant.is_valid(now))
+            .collect();
+        Response::Ok(grants)
+    })
+}
+
+#[update]
+fn approvals_revoke_one(badge_id: u128, principal_id: String) -> Response<bool> {
+    let p = match authenticated_caller() {
+        Ok(p) => p,
+        Err(err) => return Response::Err(err),
+    };
+    let user = authenticated_user(p);
+
+    if user.is_none() {
+        return Response::Err(Error::unauthorized(p));
+    }
+
+    let user = user.unwrap();
+
+    let badge = BADGES.with(|badges| badges.borrow().get(&badge_id));
+
+    if badge.is_none() {
+        return Response::Err(Error::not_found("badge", badge_id));
+    }
+
+    if user.principal_id != badge.unwrap().owner_principal.to_text() {
+        return Response::Err(Error::forbidden(
+            p,
+            format!(
+                "User with principal {} cannot revoke access grants for badge with id {}.",
+                p, badge_id
+            ),
         ));
     }
-    let requesting_principal = requesting_principal.unwrap();
 
     BADGE_ACCESS_APPROVALS.with(|approvals| {
         let mut approvals = approvals.borrow_mut();
-        if approvals.contains_key(&pending_request.badge_id) {
-            approvals
-                .get_mut(&pending_request.badge_id)
-                .unwrap()
-                .push(requesting_principal);
-        } else {
-            approvals.insert(pending_request.badge_id, vec![requesting_principal.clone()]);
+        let mut approved_for_badge = approvals.get(&badge_id).unwrap_or_default();
+        let count = approved_for_badge.0.len();
+        approved_for_badge
+            .0
+            .retain(|grant| grant.principal_id != principal_id);
+
+        if approved_for_badge.0.len() == count {
+            return Response::Err(Error::not_found("accessGrant", principal_id));
         }
+
+        approvals.insert(badge_id, approved_for_badge);
         Response::Ok(true)
     })
 }
 
 #[query]
 fn roles_get_all() -> Response<Vec<Role>> {
-    ROLES.with(|roles| Response::Ok(roles.borrow().values().cloned().collect()))
-}
-
-#[pre_upgrade]
-fn pre_upgrade() {
-    // Create an instance of stable data and persist it in stable memory
-    let stable_data = StableData {
-        principals: PRINCIPALS.with(|it| it.borrow().clone()),
-        badges: BADGES.with(|it| it.borrow().clone()),
-        access_requests: ACCESS_REQUESTS.with(|it| it.borrow().clone()),
-        badge_access_approvals: BADGE_ACCESS_APPROVALS.with(|it| it.borrow().clone()),
-    };
-
-    storage::stable_save((stable_data,)).expect("Could not save stable data.");
+    ROLES.with(|roles| Response::Ok(roles.borrow().values().collect()))
 }
 
 #[post_upgrade]
-fn post_upgrade() {
-    util::generate_organisations();
-    util::generate_roles();
-
-    // Load the stable data that was saved in pre_upgrade
-    let (stable_data,): (StableData,) =
-        storage::stable_restore().expect("Could not restore stable data.");
-
-    // Load the stable data into the new data structures
-    PRINCIPALS.with(|principals| {
-        let mut principals = principals.borrow_mut();
-        for (p, user) in stable_data.principals {
-            principals.insert(p, user);
-        }
-    });
-
-    BADGES.with(|badges| {
-        let mut badges = badges.borrow_mut();
-        for (id, badge) in stable_data.badges {
-            badges.insert(id, badge);
-        }
-    });
+fn post_upgrade(args: Option<InitArgs>) {
+    // Migrations have to run before anything else touches stable memory
+    let args = args.unwrap_or_default();
+    migration::migrate(args.administrator);
+    config::apply(args);
 
-    ACCESS_REQUESTS.with(|requests| {
-        let mut requests = requests.borrow_mut();
-        for (p, stored) in stable_data.access_requests {
-            requests.insert(p, stored);
-        }
-    });
+    index::start_rebuild();
 
-    BADGE_ACCESS_APPROVALS.with(|approvals| {
-        let mut approvals = approvals.borrow_mut();
-        for (id, principals) in stable_data.badge_access_approvals {
-            approvals.insert(id, principals);
-        }
-    });
+    signing::schedule_load_public_key(Duration::ZERO);
+    ic_cdk_timers::set_timer_interval(EXPIRY_CHECK_INTERVAL, util::expire_badges);
 }
 
 #[init]
-fn init() {
+fn init(args: Option<InitArgs>) {
+    migration::set_schema_version(migration::SCHEMA_VERSION);
+    config::apply(args.unwrap_or_default());
+
     util::generate_organisations();
     util::generate_roles();
+    index::start_rebuild();
+
+    signing::schedule_load_public_key(Duration::ZERO);
+    ic_cdk_timers::set_timer_interval(EXPIRY_CHECK_INTERVAL, util::expire_badges);
 }
@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_cdk::api::stable::{stable64_read, stable64_size};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, Storable};
+use serde::de::DeserializeOwned;
+
+use crate::model::{Config, IdKind, Organisation, Role, RoleRequest, StoredBadge, StoredUser};
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+pub const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(0);
+pub const USERS_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub const BADGES_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub const ACCESS_REQUESTS_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const BADGE_ACCESS_APPROVALS_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const ID_COUNTERS_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const ORGANISATIONS_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const ROLES_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const ROLE_REQUESTS_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const BADGE_EXPIRIES_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const BADGES_BY_OWNER_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const BADGES_BY_ISSUER_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const REQUESTS_BY_REQUESTER_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const REQUEST_OWNERS_MEMORY_ID: MemoryId = MemoryId::new(14);
+
+/// The magic bytes the memory manager writes at the start of stable memory.
+const MEMORY_MANAGER_MAGIC: &[u8; 3] = b"MGR";
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+}
+
+pub fn get(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|it| it.borrow().get(id))
+}
+
+/// has_legacy_layout returns true if stable memory holds a snapshot written with `stable_save`
+/// instead of the memory manager layout.
+/// This has to be checked before any stable structure is accessed, since initialising the
+/// memory manager overwrites the beginning of stable memory.
+pub fn has_legacy_layout() -> bool {
+    if stable64_size() == 0 {
+        return false;
+    }
+    let mut magic = [0; 3];
+    stable64_read(0, &mut magic);
+    &magic != MEMORY_MANAGER_MAGIC
+}
+
+/// StorablePrincipal wraps a principal so that it can be used as a stable map key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StorablePrincipal(pub Principal);
+
+impl From<Principal> for StorablePrincipal {
+    fn from(p: Principal) -> Self {
+        StorablePrincipal(p)
+    }
+}
+
+impl Storable for StorablePrincipal {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_slice())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        StorablePrincipal(Principal::from_slice(&bytes))
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 29,
+        is_fixed_size: false,
+    };
+}
+
+/// StorableVec wraps a list of values that is stored in stable memory using its Candid encoding.
+#[derive(Debug, Clone)]
+pub struct StorableVec<T>(pub Vec<T>);
+
+impl<T> Default for StorableVec<T> {
+    fn default() -> Self {
+        StorableVec(Vec::new())
+    }
+}
+
+impl<T: CandidType + DeserializeOwned> Storable for StorableVec<T> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(&self.0).expect("Could not encode list."))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        StorableVec(Decode!(&bytes, Vec<T>).expect("Could not decode list."))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// impl_candid_storable implements `Storable` for types that are stored using their Candid encoding.
+macro_rules! impl_candid_storable {
+    ($($t:ty),*) => {
+        $(
+            impl Storable for $t {
+                fn to_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(Encode!(self).expect(concat!("Could not encode ", stringify!($t), ".")))
+                }
+
+                fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+                    Decode!(&bytes, $t).expect(concat!("Could not decode ", stringify!($t), "."))
+                }
+
+                const BOUND: Bound = Bound::Unbounded;
+            }
+        )*
+    };
+}
+
+pub(crate) use impl_candid_storable;
+
+impl_candid_storable!(
+    StoredUser,
+    StoredBadge,
+    Organisation,
+    Role,
+    RoleRequest,
+    Config
+);
+
+impl Storable for IdKind {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(vec![*self as u8])
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        match bytes[0] {
+            0 => IdKind::Badge,
+            1 => IdKind::AccessRequest,
+            2 => IdKind::Organisation,
+            kind => panic!("Unknown id kind {}.", kind),
+        }
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1,
+        is_fixed_size: true,
+    };
+}
@@ -0,0 +1,564 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_cdk::api::time;
+use ic_cdk::storage;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
+use serde::{Deserialize, Serialize};
+
+use crate::memory::{
+    self, impl_candid_storable, Memory, StorablePrincipal, StorableVec, ACCESS_REQUESTS_MEMORY_ID,
+    BADGES_MEMORY_ID, BADGE_ACCESS_APPROVALS_MEMORY_ID, SCHEMA_VERSION_MEMORY_ID, USERS_MEMORY_ID,
+};
+use crate::model::{
+    AccessGrant, AccessRequestStatus, BadgeStatus, Claim, IdKind, Revocation, Role, RoleRequest,
+    RoleRequestStatus, StoredAccessRequest, StoredBadge, StoredUser,
+};
+use crate::{config, index, util};
+use crate::{
+    ACCESS_REQUESTS, BADGES, BADGE_ACCESS_APPROVALS, ID_COUNTERS, PRINCIPALS, ROLE_REQUESTS,
+};
+
+/// The schema version of the data currently kept in stable memory.
+///
+/// Version 1 is the snapshot written with `stable_save` in `pre_upgrade`.
+/// Version 2 keeps every map in its own stable `BTreeMap`.
+/// Version 3 persists organisations and roles instead of regenerating them on every upgrade.
+/// Version 4 stores the owner and the issuer of a badge by reference.
+/// Version 5 keeps decided access requests with their status instead of removing them.
+/// Version 6 records when claim access was granted and until when it is valid.
+/// Version 7 indexes the badges that have yet to expire by their expiry date.
+/// Version 8 stores the organisation of a user by reference.
+/// Version 9 indexes badges by owner and issuer and access requests by requester in stable memory.
+/// Version 10 turns privileged roles that users granted themselves into pending role requests.
+pub const SCHEMA_VERSION: u32 = 10;
+
+thread_local! {
+    static STORED_SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(memory::get(SCHEMA_VERSION_MEMORY_ID), SCHEMA_VERSION)
+            .expect("Could not initialise schema version.")
+    );
+}
+
+/// StableDataV1 is the snapshot of all maps persisted by schema version 1.
+/// Its types mirror the records of that version exactly, since the snapshot cannot be decoded otherwise.
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct StableDataV1 {
+    pub principals: BTreeMap<Principal, UserV1>,
+
+    pub badges: BTreeMap<u128, BadgeV1>,
+
+    pub access_requests: BTreeMap<Principal, Vec<StoredAccessRequestV4>>,
+
+    pub badge_access_approvals: BTreeMap<u128, Vec<Principal>>,
+}
+
+/// BadgeV1 is a badge as persisted by schema version 1, which only knew whether it was revoked.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct BadgeV1 {
+    #[serde(rename = "badgeID")]
+    pub id: u128,
+
+    pub title: String,
+
+    pub description: Option<String>,
+
+    #[serde(rename = "badgeType")]
+    pub badge_type: u16,
+
+    pub issuer: OrganisationV1,
+
+    pub owner: UserV1,
+
+    #[serde(rename = "isRevoked")]
+    pub is_revoked: bool,
+
+    pub claims: Vec<Claim>,
+
+    #[serde(rename = "signedBy")]
+    pub signed_by: Vec<String>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+}
+
+impl From<BadgeV1> for BadgeV3 {
+    /// Revoked badges stay revoked, without a recorded reason, all other badges become active.
+    fn from(badge: BadgeV1) -> Self {
+        BadgeV3 {
+            id: badge.id,
+            title: badge.title,
+            description: badge.description,
+            badge_type: badge.badge_type,
+            issuer: OrganisationRefV3 {
+                id: badge.issuer.id,
+            },
+            owner: UserRefV3 {
+                principal_id: badge.owner.principal_id,
+            },
+            status: if badge.is_revoked {
+                BadgeStatus::Revoked
+            } else {
+                BadgeStatus::Active
+            },
+            revocation: None,
+            claims: badge.claims,
+            signed_by: badge.signed_by,
+            created_at: badge.created_at,
+            expires_at: None,
+        }
+    }
+}
+
+/// OrganisationV1 is an organisation as embedded in the users and badges of schema version 1.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct OrganisationV1 {
+    #[serde(rename = "organisationID")]
+    pub id: u128,
+
+    pub name: String,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+}
+
+/// UserV1 is a user as persisted by schema version 1.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct UserV1 {
+    pub name: String,
+
+    pub email: String,
+
+    #[serde(rename = "principalID")]
+    pub principal_id: String,
+
+    pub organisation: OrganisationV1,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    pub roles: Vec<Role>,
+}
+
+impl From<UserV1> for UserV7 {
+    fn from(user: UserV1) -> Self {
+        UserV7 {
+            name: user.name,
+            email: user.email,
+            principal_id: user.principal_id,
+            organisation: OrganisationRefV3 {
+                id: user.organisation.id,
+            },
+            created_at: user.created_at,
+            roles: user.roles,
+        }
+    }
+}
+
+/// UserV7 is a user as persisted up to schema version 7, with the organisation embedded.
+/// Only the ID of the embedded organisation is decoded.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct UserV7 {
+    pub name: String,
+
+    pub email: String,
+
+    #[serde(rename = "principalID")]
+    pub principal_id: String,
+
+    pub organisation: OrganisationRefV3,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    pub roles: Vec<Role>,
+}
+
+/// BadgeV3 is a badge as persisted up to schema version 3, with the owner and the issuer embedded.
+/// Only the fields needed to resolve them are decoded from the embedded records.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct BadgeV3 {
+    #[serde(rename = "badgeID")]
+    pub id: u128,
+
+    pub title: String,
+
+    pub description: Option<String>,
+
+    #[serde(rename = "badgeType")]
+    pub badge_type: u16,
+
+    pub issuer: OrganisationRefV3,
+
+    pub owner: UserRefV3,
+
+    pub status: BadgeStatus,
+
+    pub revocation: Option<Revocation>,
+
+    pub claims: Vec<Claim>,
+
+    #[serde(rename = "signedBy")]
+    pub signed_by: Vec<String>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct OrganisationRefV3 {
+    #[serde(rename = "organisationID")]
+    pub id: u128,
+}
+
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct UserRefV3 {
+    #[serde(rename = "principalID")]
+    pub principal_id: String,
+}
+
+/// StoredAccessRequestV4 is an access request as persisted up to schema version 4.
+/// Only pending requests were kept, approved requests were removed.
+#[derive(Clone, CandidType, Serialize, Deserialize)]
+pub struct StoredAccessRequestV4 {
+    pub id: u128,
+
+    pub principal_id: String,
+
+    pub badge_id: u128,
+
+    pub created_at: u64,
+}
+
+impl_candid_storable!(BadgeV3, UserV7);
+
+/// users_v7 opens the users memory with the layout used up to schema version 7.
+/// It must not be used once the users map of the current version has been accessed.
+fn users_v7() -> StableBTreeMap<StorablePrincipal, UserV7, Memory> {
+    StableBTreeMap::init(memory::get(USERS_MEMORY_ID))
+}
+
+/// badges_v3 opens the badges memory with the layout used up to schema version 3.
+/// It must not be used once the badges map of the current version has been accessed.
+fn badges_v3() -> StableBTreeMap<u128, BadgeV3, Memory> {
+    StableBTreeMap::init(memory::get(BADGES_MEMORY_ID))
+}
+
+/// access_requests_v4 opens the access requests memory with the layout used up to schema version 4.
+/// It must not be used once the access requests map of the current version has been accessed.
+fn access_requests_v4(
+) -> StableBTreeMap<StorablePrincipal, StorableVec<StoredAccessRequestV4>, Memory> {
+    StableBTreeMap::init(memory::get(ACCESS_REQUESTS_MEMORY_ID))
+}
+
+/// badge_access_approvals_v5 opens the approvals memory with the layout used up to schema version 5.
+/// It must not be used once the approvals map of the current version has been accessed.
+fn badge_access_approvals_v5() -> StableBTreeMap<u128, StorableVec<Principal>, Memory> {
+    StableBTreeMap::init(memory::get(BADGE_ACCESS_APPROVALS_MEMORY_ID))
+}
+
+pub fn schema_version() -> u32 {
+    STORED_SCHEMA_VERSION.with(|it| *it.borrow().get())
+}
+
+pub fn set_schema_version(version: u32) {
+    STORED_SCHEMA_VERSION.with(|it| {
+        it.borrow_mut()
+            .set(version)
+            .expect("Could not save schema version.");
+    });
+}
+
+/// migrate brings the data in stable memory up to the current schema version,
+/// applying every migration between the stored version and `SCHEMA_VERSION` in order.
+/// The administrator is the one passed in the upgrade argument, which is not stored yet.
+pub fn migrate(administrator: Option<Principal>) {
+    // The legacy snapshot has to be read before the memory manager claims stable memory.
+    let mut legacy = if memory::has_legacy_layout() {
+        let (data,): (StableDataV1,) =
+            storage::stable_restore().expect("Could not restore stable data.");
+        set_schema_version(1);
+        Some(data)
+    } else {
+        None
+    };
+
+    let mut version = schema_version();
+
+    if version > SCHEMA_VERSION {
+        panic!(
+            "Stable memory schema version {} is newer than the supported version {}.",
+            version, SCHEMA_VERSION
+        );
+    }
+
+    while version < SCHEMA_VERSION {
+        match version {
+            1 => migrate_v1_to_v2(legacy.take().expect("Missing stable data for version 1.")),
+            2 => migrate_v2_to_v3(),
+            3 => migrate_v3_to_v4(),
+            4 => migrate_v4_to_v5(),
+            5 => migrate_v5_to_v6(),
+            6 => migrate_v6_to_v7(),
+            7 => migrate_v7_to_v8(),
+            8 => migrate_v8_to_v9(),
+            9 => migrate_v9_to_v10(administrator),
+            _ => panic!("No migration from schema version {}.", version),
+        }
+        version += 1;
+        set_schema_version(version);
+    }
+}
+
+/// migrate_v1_to_v2 moves the snapshot maps into their stable `BTreeMap`s,
+/// deriving the status of every badge from whether it was revoked.
+///
+/// Version 1 did not keep ID counters, it numbered badges and access requests by counting them.
+/// The counters are seeded with the highest ID in use, so new IDs never overwrite existing entities.
+/// Access request IDs were only counted per badge owner, so requests sharing an ID get new IDs.
+fn migrate_v1_to_v2(data: StableDataV1) {
+    let mut users = users_v7();
+    for (p, user) in data.principals {
+        users.insert(p.into(), UserV7::from(user));
+    }
+
+    let last_badge_id = data.badges.keys().max().copied().unwrap_or(0);
+    let mut badges = badges_v3();
+    for (id, badge) in data.badges {
+        badges.insert(id, BadgeV3::from(badge));
+    }
+
+    let mut last_request_id = data
+        .access_requests
+        .values()
+        .flatten()
+        .map(|r| r.id)
+        .max()
+        .unwrap_or(0);
+    let mut request_ids = BTreeSet::new();
+    let mut requests = access_requests_v4();
+    for (p, mut stored) in data.access_requests {
+        for request in stored.iter_mut() {
+            if !request_ids.insert(request.id) {
+                last_request_id += 1;
+                request.id = last_request_id;
+                request_ids.insert(request.id);
+            }
+        }
+        requests.insert(p.into(), StorableVec(stored));
+    }
+
+    let mut approvals = badge_access_approvals_v5();
+    for (id, principals) in data.badge_access_approvals {
+        approvals.insert(id, StorableVec(principals));
+    }
+
+    ID_COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        counters.insert(IdKind::Badge, last_badge_id);
+        counters.insert(IdKind::AccessRequest, last_request_id);
+    });
+}
+
+/// migrate_v2_to_v3 persists the organisations and roles that version 2 regenerated on every upgrade.
+fn migrate_v2_to_v3() {
+    // Version 2 numbered the generated organisations from 1 and users and badges refer to
+    // these IDs, so the organisations are seeded again from an empty counter.
+    ID_COUNTERS.with(|counters| counters.borrow_mut().remove(&IdKind::Organisation));
+
+    util::generate_organisations();
+    util::generate_roles();
+}
+
+/// migrate_v3_to_v4 replaces the owner and the issuer embedded in every badge with their IDs.
+fn migrate_v3_to_v4() {
+    let legacy: Vec<BadgeV3> = badges_v3().values().collect();
+
+    BADGES.with(|badges| {
+        let mut badges = badges.borrow_mut();
+        for badge in legacy {
+            let owner_principal = Principal::from_str(&badge.owner.principal_id)
+                .expect("Invalid owner principal in stored badge.");
+            badges.insert(
+                badge.id,
+                StoredBadge {
+                    id: badge.id,
+                    title: badge.title,
+                    description: badge.description,
+                    badge_type: badge.badge_type,
+                    issuer_id: badge.issuer.id,
+                    owner_principal,
+                    status: badge.status,
+                    revocation: badge.revocation,
+                    claims: badge.claims,
+                    signed_by: badge.signed_by,
+                    created_at: badge.created_at,
+                    expires_at: badge.expires_at,
+                    provenance: None,
+                    signature: None,
+                },
+            );
+        }
+    });
+}
+
+/// migrate_v4_to_v5 marks every stored access request as pending.
+fn migrate_v4_to_v5() {
+    let legacy: Vec<(StorablePrincipal, StorableVec<StoredAccessRequestV4>)> =
+        access_requests_v4().iter().collect();
+
+    ACCESS_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        for (owner, StorableVec(stored)) in legacy {
+            let converted = stored
+                .into_iter()
+                .map(|r| StoredAccessRequest {
+                    id: r.id,
+                    principal_id: r.principal_id,
+                    badge_id: r.badge_id,
+                    claim_keys: None,
+                    purpose: None,
+                    message: None,
+                    legal_basis: None,
+                    status: AccessRequestStatus::Pending,
+                    created_at: r.created_at,
+                    decided_at: None,
+                })
+                .collect();
+            requests.insert(owner, StorableVec(converted));
+        }
+    });
+}
+
+/// migrate_v5_to_v6 turns every approved principal into an access grant without expiry.
+/// The time of the original approval was not recorded, so the time of the migration is used instead.
+fn migrate_v5_to_v6() {
+    let legacy: Vec<(u128, StorableVec<Principal>)> = badge_access_approvals_v5().iter().collect();
+    let now = time();
+
+    BADGE_ACCESS_APPROVALS.with(|approvals| {
+        let mut approvals = approvals.borrow_mut();
+        for (badge_id, StorableVec(principals)) in legacy {
+            let grants = principals
+                .into_iter()
+                .map(|p| AccessGrant {
+                    principal_id: p.to_string(),
+                    granted_at: now,
+                    expires_at: None,
+                    claim_keys: None,
+                })
+                .collect();
+            approvals.insert(badge_id, StorableVec(grants));
+        }
+    });
+}
+
+/// migrate_v6_to_v7 adds every badge that can still expire to the expiry index.
+fn migrate_v6_to_v7() {
+    BADGES.with(|badges| {
+        for badge in badges.borrow().values() {
+            index::update_badge_expiry(None, &badge);
+        }
+    });
+}
+
+/// migrate_v7_to_v8 replaces the organisation embedded in every user with its ID.
+fn migrate_v7_to_v8() {
+    let legacy: Vec<(StorablePrincipal, UserV7)> = users_v7().iter().collect();
+
+    PRINCIPALS.with(|principals| {
+        let mut principals = principals.borrow_mut();
+        for (p, user) in legacy {
+            principals.insert(
+                p,
+                StoredUser {
+                    name: user.name,
+                    email: user.email,
+                    principal_id: user.principal_id,
+                    organisation_id: user.organisation.id,
+                    created_at: user.created_at,
+                    roles: user.roles,
+                },
+            );
+        }
+    });
+}
+
+/// migrate_v8_to_v9 adds every badge and access request to the stable indexes.
+fn migrate_v8_to_v9() {
+    BADGES.with(|badges| {
+        for badge in badges.borrow().values() {
+            index::update_badge_references(None, &badge);
+        }
+    });
+
+    ACCESS_REQUESTS.with(|requests| {
+        for (owner, StorableVec(stored)) in requests.borrow().iter() {
+            for request in stored.iter() {
+                index::update_access_request(owner.0, request);
+            }
+        }
+    });
+}
+
+/// migrate_v9_to_v10 removes the privileged roles that users declared for themselves when registering,
+/// before roles had to be approved, and requests them again from the administrators of their organisation.
+/// Roles granted by an approved role request are kept, and so are the roles of the configured administrator,
+/// who would otherwise be left without anyone to approve their request.
+fn migrate_v9_to_v10(administrator: Option<Principal>) {
+    let administrator = administrator
+        .or(config::get().administrator)
+        .map(|p| p.to_string());
+    let users: Vec<(StorablePrincipal, StoredUser)> =
+        PRINCIPALS.with(|principals| principals.borrow().iter().collect());
+    let now = time();
+
+    for (p, mut user) in users {
+        if administrator.as_ref() == Some(&user.principal_id) {
+            continue;
+        }
+
+        let previous = ROLE_REQUESTS.with(|requests| requests.borrow().get(&p));
+        let is_approved = |role: &Role| {
+            matches!(&previous, Some(request) if request.status == RoleRequestStatus::Approved
+                && request.roles.iter().any(|it| it.id == role.id))
+        };
+
+        let (declared, roles): (Vec<Role>, Vec<Role>) = user
+            .roles
+            .into_iter()
+            .partition(|role| role.is_privileged() && !is_approved(role));
+        user.roles = roles;
+        if declared.is_empty() {
+            continue;
+        }
+
+        let mut request = match previous {
+            Some(request) if request.status == RoleRequestStatus::Pending => request,
+            _ => RoleRequest {
+                principal_id: user.principal_id.clone(),
+                organisation_id: user.organisation_id,
+                roles: Vec::new(),
+                status: RoleRequestStatus::Pending,
+                created_at: now,
+                decided_by: None,
+                decided_at: None,
+            },
+        };
+        for role in declared {
+            if !request.roles.iter().any(|it| it.id == role.id) {
+                request.roles.push(role);
+            }
+        }
+
+        ROLE_REQUESTS.with(|requests| requests.borrow_mut().insert(p, request));
+        PRINCIPALS.with(|principals| principals.borrow_mut().insert(p, user));
+    }
+}
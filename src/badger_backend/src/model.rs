@@ -1,10 +1,7 @@
-use candid::CandidType;
+use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
-use crate::{
-    AccessRequestsMap, BadgeAccessApprovalsMap, BadgesMap, UsersMap, ADMINISTRATOR_ROLE_ID,
-    COMPANY_ROLE_ID, LECTURER_ROLE_ID, STUDENT_ROLE_ID,
-};
+use crate::{ADMINISTRATOR_ROLE_ID, COMPANY_ROLE_ID, LECTURER_ROLE_ID, STUDENT_ROLE_ID};
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
 pub enum Response<T> {
@@ -12,7 +9,151 @@ pub enum Response<T> {
     Ok(T),
 
     #[serde(rename = "error")]
-    Err(String),
+    Err(Error),
+}
+
+/// Error describes why a request could not be handled.
+/// Variants without a structured cause carry a human readable message.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub enum Error {
+    /// The entity with the given ID does not exist.
+    #[serde(rename = "notFound")]
+    NotFound { entity: String, id: String },
+
+    /// The caller is not registered as a user.
+    #[serde(rename = "unauthorized")]
+    Unauthorized { principal: String },
+
+    /// The caller is not allowed to perform the action.
+    #[serde(rename = "forbidden")]
+    Forbidden { principal: String, message: String },
+
+    /// An argument of the request is not valid.
+    #[serde(rename = "invalidInput")]
+    InvalidInput { field: String, message: String },
+
+    /// The request conflicts with the current state of an entity.
+    #[serde(rename = "conflict")]
+    Conflict { message: String },
+
+    /// A service the request depends on, like threshold signing, is not available.
+    #[serde(rename = "unavailable")]
+    Unavailable { message: String },
+}
+
+impl Error {
+    pub fn not_found(entity: &str, id: impl ToString) -> Self {
+        Error::NotFound {
+            entity: entity.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn unauthorized(principal: Principal) -> Self {
+        Error::Unauthorized {
+            principal: principal.to_string(),
+        }
+    }
+
+    pub fn forbidden(principal: Principal, message: String) -> Self {
+        Error::Forbidden {
+            principal: principal.to_string(),
+            message,
+        }
+    }
+
+    pub fn invalid_input(field: &str, message: impl Into<String>) -> Self {
+        Error::InvalidInput {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Error::Conflict {
+            message: message.into(),
+        }
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Error::Unavailable {
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, CandidType, Serialize, Deserialize)]
+pub struct PageRequest {
+    /// The cursor returned with the previous page, or `None` for the first page.
+    pub cursor: Option<String>,
+
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+
+    /// The cursor of the next page, or `None` if this is the last page.
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+
+    /// The number of items on all pages.
+    pub total: u64,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            total: self.total,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum SortOrder {
+    #[serde(rename = "asc")]
+    Asc,
+
+    #[serde(rename = "desc")]
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum BadgeSortBy {
+    #[serde(rename = "createdAt")]
+    CreatedAt,
+
+    #[serde(rename = "title")]
+    Title,
+
+    #[serde(rename = "ownerName")]
+    OwnerName,
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Serialize, Deserialize)]
+pub struct BadgeSort {
+    pub by: BadgeSortBy,
+
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum UserSortBy {
+    #[serde(rename = "createdAt")]
+    CreatedAt,
+
+    #[serde(rename = "name")]
+    Name,
+}
+
+#[derive(Debug, Clone, Copy, CandidType, Serialize, Deserialize)]
+pub struct UserSort {
+    pub by: UserSortBy,
+
+    pub order: SortOrder,
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -40,16 +181,478 @@ pub struct Badge {
     #[serde(rename = "owner")]
     pub owner: User,
 
-    #[serde(rename = "isRevoked")]
-    pub is_revoked: bool,
+    pub status: BadgeStatus,
+
+    pub revocation: Option<Revocation>,
 
     pub claims: Vec<Claim>,
 
+    /// The keys of the claims that are not disclosed to the caller.
+    #[serde(rename = "withheldClaimKeys")]
+    pub withheld_claim_keys: Vec<String>,
+
     #[serde(rename = "signedBy")]
     pub signed_by: Vec<String>,
 
     #[serde(rename = "createdAt")]
     pub created_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+
+    /// Where the badge was imported from, or `None` if it was issued here.
+    pub provenance: Option<Provenance>,
+}
+
+/// BadgeSummary describes a badge without its owner and claims.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct BadgeSummary {
+    #[serde(rename = "badgeID")]
+    pub id: u128,
+
+    pub title: String,
+
+    #[serde(rename = "badgeType")]
+    pub badge_type: u16,
+
+    pub issuer: Organisation,
+
+    pub status: BadgeStatus,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+}
+
+impl From<&Badge> for BadgeSummary {
+    fn from(badge: &Badge) -> Self {
+        BadgeSummary {
+            id: badge.id,
+            title: badge.title.clone(),
+            badge_type: badge.badge_type,
+            issuer: badge.issuer.clone(),
+            status: badge.status,
+            expires_at: badge.expires_at,
+        }
+    }
+}
+
+/// BadgeVerification is the publicly verifiable state of a badge.
+/// It never includes the owner or the claims of the badge.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct BadgeVerification {
+    #[serde(rename = "badgeID")]
+    pub id: u128,
+
+    /// True if the badge is active, i.e. it is neither suspended, revoked nor expired.
+    /// For imported badges this does not vouch for the original credential, see `provenance`.
+    pub valid: bool,
+
+    pub title: String,
+
+    pub issuer: Organisation,
+
+    pub status: BadgeStatus,
+
+    #[serde(rename = "issuedAt")]
+    pub issued_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: Option<u64>,
+
+    #[serde(rename = "revocationReason")]
+    pub revocation_reason: Option<RevocationReason>,
+
+    /// The original credential of an imported badge, whose issuer did not sign the badge.
+    pub provenance: Option<Provenance>,
+}
+
+impl BadgeVerification {
+    pub fn new(badge: &StoredBadge, issuer: Organisation) -> Self {
+        BadgeVerification {
+            id: badge.id,
+            valid: badge.status == BadgeStatus::Active,
+            title: badge.title.clone(),
+            issuer,
+            status: badge.status,
+            issued_at: badge.created_at,
+            expires_at: badge.expires_at,
+            revoked_at: badge.revocation.as_ref().map(|r| r.revoked_at),
+            revocation_reason: badge.revocation.as_ref().map(|r| r.reason),
+            provenance: badge.provenance.clone(),
+        }
+    }
+}
+
+/// CertifiedBadgeState is the part of a badge whose hash is certified.
+/// The status is the stored one, so a badge past its expiry date may still be certified as active
+/// until the expiry check has run; clients compare `expiresAt` with the time of the certificate.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct CertifiedBadgeState {
+    #[serde(rename = "badgeID")]
+    pub id: u128,
+
+    #[serde(rename = "issuerID")]
+    pub issuer_id: u128,
+
+    pub status: BadgeStatus,
+
+    #[serde(rename = "issuedAt")]
+    pub issued_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: Option<u64>,
+
+    /// True if the badge was imported from an external credential instead of issued by the canister.
+    pub imported: bool,
+}
+
+impl From<&StoredBadge> for CertifiedBadgeState {
+    fn from(badge: &StoredBadge) -> Self {
+        CertifiedBadgeState {
+            id: badge.id,
+            issuer_id: badge.issuer_id,
+            status: badge.status,
+            issued_at: badge.created_at,
+            expires_at: badge.expires_at,
+            revoked_at: badge.revocation.as_ref().map(|r| r.revoked_at),
+            imported: badge.provenance.is_some(),
+        }
+    }
+}
+
+/// CertifiedBadgeVerification is a badge verification that can be checked offline.
+/// The SHA-256 hash of the Candid encoding of `state` is found in `witness` under the path
+/// `badges/<badge ID as 16 big-endian bytes>`, and the root hash of `witness` is the certified
+/// data of the canister signed in `certificate`.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct CertifiedBadgeVerification {
+    pub verification: BadgeVerification,
+
+    pub state: CertifiedBadgeState,
+
+    /// The CBOR encoded system certificate, only present in query calls.
+    pub certificate: Option<Vec<u8>>,
+
+    /// The CBOR encoded hash tree.
+    pub witness: Vec<u8>,
+}
+
+/// HttpRequest is a request forwarded by the HTTP gateway.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct HttpRequest {
+    pub method: String,
+
+    pub url: String,
+
+    pub headers: Vec<(String, String)>,
+
+    pub body: Vec<u8>,
+}
+
+/// HttpResponse is the response returned to the HTTP gateway.
+#[derive(Debug, Clone, CandidType, Serialize)]
+pub struct HttpResponse {
+    pub status_code: u16,
+
+    pub headers: Vec<(String, String)>,
+
+    pub body: Vec<u8>,
+}
+
+/// StoredBadge is the badge as kept in stable memory.
+/// The owner and the issuer are stored by reference and resolved when the badge is returned.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct StoredBadge {
+    pub id: u128,
+
+    pub title: String,
+
+    pub description: Option<String>,
+
+    pub badge_type: u16,
+
+    pub issuer_id: u128,
+
+    pub owner_principal: Principal,
+
+    pub status: BadgeStatus,
+
+    pub revocation: Option<Revocation>,
+
+    pub claims: Vec<Claim>,
+
+    pub signed_by: Vec<String>,
+
+    pub created_at: u64,
+
+    pub expires_at: Option<u64>,
+
+    /// Badges stored before imports were supported have no provenance.
+    pub provenance: Option<Provenance>,
+
+    /// The canister signature, or `None` for imported badges and badges issued before signing.
+    pub signature: Option<BadgeSignature>,
+}
+
+impl StoredBadge {
+    pub fn has_claim(&self, key: &str) -> bool {
+        self.claims.iter().any(|claim| claim.key == key)
+    }
+
+    /// Checks if the badge is expired at the given time.
+    /// A badge is expired once it has been marked as such or its expiry date has passed
+    /// while it was still allowed to expire.
+    pub fn has_expired(&self, now: u64) -> bool {
+        self.status == BadgeStatus::Expired
+            || (self.status.can_transition_to(BadgeStatus::Expired)
+                && matches!(self.expires_at, Some(expires_at) if expires_at <= now))
+    }
+
+    /// Moves the badge to the next status if the transition is allowed.
+    pub fn transition_to(&mut self, next: BadgeStatus) -> Result<(), Error> {
+        if !self.status.can_transition_to(next) {
+            return Err(Error::conflict(format!(
+                "Badge with id {} cannot transition from {:?} to {:?}.",
+                self.id, self.status, next
+            )));
+        }
+        self.status = next;
+        Ok(())
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, CandidType, Serialize, Deserialize,
+)]
+pub enum BadgeStatus {
+    #[serde(rename = "active")]
+    Active,
+
+    #[serde(rename = "suspended")]
+    Suspended,
+
+    #[serde(rename = "revoked")]
+    Revoked,
+
+    #[serde(rename = "expired")]
+    Expired,
+}
+
+impl BadgeStatus {
+    /// Checks if a badge in this status can move to the next status.
+    /// Active and suspended badges can be suspended, reinstated, revoked or expire.
+    /// Revoked and expired badges are final.
+    pub fn can_transition_to(&self, next: BadgeStatus) -> bool {
+        matches!(
+            (self, next),
+            (BadgeStatus::Active, BadgeStatus::Suspended)
+                | (BadgeStatus::Suspended, BadgeStatus::Active)
+                | (
+                    BadgeStatus::Active | BadgeStatus::Suspended,
+                    BadgeStatus::Revoked | BadgeStatus::Expired
+                )
+        )
+    }
+}
+
+/// BadgeSearch selects the badges matching all of the given filters.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct BadgeSearch {
+    /// Text contained in the title or description, ignoring case.
+    pub text: Option<String>,
+
+    pub claim: Option<ClaimFilter>,
+
+    #[serde(rename = "badgeType")]
+    pub badge_type: Option<u16>,
+
+    pub status: Option<BadgeStatus>,
+
+    #[serde(rename = "issuerID")]
+    pub issuer_id: Option<u128>,
+
+    #[serde(rename = "createdAfter")]
+    pub created_after: Option<u64>,
+
+    #[serde(rename = "createdBefore")]
+    pub created_before: Option<u64>,
+}
+
+impl BadgeSearch {
+    /// Checks if the badge matches all filters of the search.
+    pub fn matches(&self, badge: &StoredBadge) -> bool {
+        let text_filter = |badge: &StoredBadge| match &self.text {
+            Some(text) => {
+                let text = text.to_lowercase();
+                badge.title.to_lowercase().contains(&text)
+                    || matches!(&badge.description, Some(d) if d.to_lowercase().contains(&text))
+            }
+            None => true,
+        };
+
+        let claim_filter = |badge: &StoredBadge| match &self.claim {
+            Some(filter) => badge.claims.iter().any(|claim| {
+                claim.key == filter.key
+                    && filter
+                        .value
+                        .as_ref()
+                        .is_none_or(|value| *value == claim.value)
+            }),
+            None => true,
+        };
+
+        text_filter(badge)
+            && claim_filter(badge)
+            && self.badge_type.is_none_or(|it| badge.badge_type == it)
+            && self.status.is_none_or(|it| badge.status == it)
+            && self.issuer_id.is_none_or(|it| badge.issuer_id == it)
+            && self.created_after.is_none_or(|it| badge.created_at >= it)
+            && self.created_before.is_none_or(|it| badge.created_at <= it)
+    }
+}
+
+/// BadgeSignature is the canister signature over the `SignedBadgeContent` of a badge.
+/// The nonce is never disclosed, since the salts of all claims are derived from it.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct BadgeSignature {
+    pub nonce: Vec<u8>,
+
+    /// The signature over the SHA-256 hash of the Candid encoded content.
+    pub signature: Vec<u8>,
+
+    pub key_name: String,
+
+    pub signed_at: u64,
+}
+
+/// SignedBadgeContent is the canonical serialisation of a badge that the canister signs.
+/// Claims are only included as salted digests, so that the signature can be checked
+/// against the disclosed claims without revealing the withheld ones.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct SignedBadgeContent {
+    #[serde(rename = "canisterID")]
+    pub canister_id: Principal,
+
+    #[serde(rename = "badgeID")]
+    pub id: u128,
+
+    pub title: String,
+
+    pub description: Option<String>,
+
+    #[serde(rename = "badgeType")]
+    pub badge_type: u16,
+
+    #[serde(rename = "issuerID")]
+    pub issuer_id: u128,
+
+    #[serde(rename = "ownerID")]
+    pub owner_principal: Principal,
+
+    pub claims: Vec<ClaimDigest>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+}
+
+/// ClaimDigest is the SHA-256 hash of the salt of the claim followed by its value.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct ClaimDigest {
+    pub key: String,
+
+    pub digest: Vec<u8>,
+}
+
+/// SigningPublicKey is the public key that badge signatures are checked against.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct SigningPublicKey {
+    /// The signature algorithm, `ecdsa-secp256k1-sha256`.
+    pub algorithm: String,
+
+    #[serde(rename = "keyName")]
+    pub key_name: String,
+
+    /// The public key in SEC1 compressed form.
+    #[serde(rename = "publicKey")]
+    pub public_key: Vec<u8>,
+}
+
+/// Provenance describes the external credential an imported badge was created from.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct Provenance {
+    pub source: BadgeSource,
+
+    /// The ID of the original credential.
+    #[serde(rename = "credentialID")]
+    pub credential_id: Option<String>,
+
+    /// The ID of the original issuer profile.
+    #[serde(rename = "issuerID")]
+    pub issuer_id: Option<String>,
+
+    #[serde(rename = "issuerName")]
+    pub issuer_name: String,
+
+    /// The narrative of the criteria of the original achievement.
+    pub criteria: Option<String>,
+
+    pub alignments: Vec<Alignment>,
+
+    /// The principal of the administrator who imported the badge.
+    #[serde(rename = "importedBy")]
+    pub imported_by: String,
+
+    #[serde(rename = "importedAt")]
+    pub imported_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum BadgeSource {
+    #[serde(rename = "openBadges")]
+    OpenBadges,
+}
+
+/// Alignment links an achievement to a competency or standard of a framework.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct Alignment {
+    #[serde(rename = "targetName")]
+    pub target_name: String,
+
+    #[serde(rename = "targetUrl")]
+    pub target_url: String,
+
+    #[serde(rename = "targetFramework")]
+    pub target_framework: Option<String>,
+}
+
+/// NewImportedBadge is an externally issued Open Badges credential to import for a user.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct NewImportedBadge {
+    #[serde(rename = "ownerID")]
+    pub owner_id: String,
+
+    #[serde(rename = "badgeType")]
+    pub badge_type: u16,
+
+    /// The Open Badges 3.0 credential as JSON.
+    pub credential: String,
+}
+
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct ClaimFilter {
+    pub key: String,
+
+    /// The value of the claim, or `None` to match any value.
+    pub value: Option<String>,
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -68,6 +671,47 @@ pub struct NewBadge {
     pub owner_id: String,
 
     pub claims: Vec<Claim>,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum RevocationReason {
+    #[serde(rename = "issuedInError")]
+    IssuedInError,
+
+    #[serde(rename = "academicMisconduct")]
+    AcademicMisconduct,
+
+    #[serde(rename = "superseded")]
+    Superseded,
+
+    #[serde(rename = "requestedByOwner")]
+    RequestedByOwner,
+
+    #[serde(rename = "other")]
+    Other,
+}
+
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct NewRevocation {
+    pub reason: RevocationReason,
+
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct Revocation {
+    pub reason: RevocationReason,
+
+    pub details: Option<String>,
+
+    #[serde(rename = "revokedBy")]
+    pub revoked_by: String,
+
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: u64,
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -77,8 +721,38 @@ pub struct Organisation {
 
     pub name: String,
 
+    pub description: Option<String>,
+
+    pub website: Option<String>,
+
+    pub country: Option<String>,
+
+    pub logo: Option<FileLocation>,
+
     #[serde(rename = "createdAt")]
     pub created_at: u64,
+
+    #[serde(rename = "archivedAt")]
+    pub archived_at: Option<u64>,
+}
+
+impl Organisation {
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+}
+
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct NewOrganisation {
+    pub name: String,
+
+    pub description: Option<String>,
+
+    pub website: Option<String>,
+
+    pub country: Option<String>,
+
+    pub logo: Option<FileLocation>,
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -124,15 +798,22 @@ impl User {
         self.is_admin() || (self.is_lecturer() && self.organisation.id == badge.issuer_id)
     }
 
+    /// Checks if the user can change the status of the badge.
+    /// A badge can be revoked, suspended or reinstated by an admin or a lecturer.
+    /// Furthermore, the lecturer has to be from the same organisation as the issuer.
+    pub fn can_change_status(&self, badge: &StoredBadge) -> bool {
+        (self.is_admin() || self.is_lecturer()) && self.organisation.id == badge.issuer_id
+    }
+
     /// Checks if the user has access to the badge.
     /// If the user is an administrator, they have access to all badges.
     /// If the user is a lecturer, they have access to all badges issued by their organisation.
     /// If the user is a student, they have access to all badges they own.
-    pub fn has_badge_access(&self, badge: &Badge) -> bool {
+    pub fn has_badge_access(&self, badge: &StoredBadge) -> bool {
         self.is_admin()
             || self.is_company() // Companies can access all badges however claim masking should be done afterwards
-            || (self.is_lecturer() && self.organisation.id == badge.issuer.id)
-            || (self.is_student() && self.principal_id == badge.owner.principal_id)
+            || (self.is_lecturer() && self.organisation.id == badge.issuer_id)
+            || (self.is_student() && self.principal_id == badge.owner_principal.to_text())
     }
 
     /// Checks if the user has access to the user.
@@ -146,6 +827,48 @@ impl User {
                 && (self.organisation.id == other_user.organisation.id || other_user.is_student()))
             || (self.is_student() && self.principal_id == other_user.principal_id)
     }
+
+    /// Checks if the user can change the roles and organisation of the other user.
+    /// Only administrators can manage users, and only those from their own organisation.
+    pub fn can_manage_user(&self, other_user: &User) -> bool {
+        self.is_admin() && self.organisation.id == other_user.organisation.id
+    }
+}
+
+/// StoredUser is the user as kept in stable memory.
+/// The organisation is stored by reference, so that changes to it are visible on every user.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct StoredUser {
+    pub name: String,
+
+    pub email: String,
+
+    pub principal_id: String,
+
+    pub organisation_id: u128,
+
+    pub created_at: u64,
+
+    pub roles: Vec<Role>,
+}
+
+impl StoredUser {
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|r| r.id == ADMINISTRATOR_ROLE_ID)
+    }
+}
+
+impl From<&User> for StoredUser {
+    fn from(user: &User) -> Self {
+        StoredUser {
+            name: user.name.clone(),
+            email: user.email.clone(),
+            principal_id: user.principal_id.clone(),
+            organisation_id: user.organisation.id,
+            created_at: user.created_at,
+            roles: user.roles.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -160,6 +883,13 @@ pub struct NewUser {
     pub roles: Vec<u128>,
 }
 
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct UserUpdate {
+    pub name: String,
+
+    pub email: String,
+}
+
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
 pub struct FileLocation {
     pub location: String,
@@ -173,7 +903,86 @@ pub struct StoredAccessRequest {
 
     pub badge_id: u128,
 
+    /// The keys of the requested claims, or `None` for requests made before claims could be selected,
+    /// which asked for all claims.
+    pub claim_keys: Option<Vec<String>>,
+
+    /// Why the company requests access, or `None` for requests made before a purpose was required.
+    pub purpose: Option<String>,
+
+    pub message: Option<String>,
+
+    /// The legal basis of the request, or `None` for requests made before it was required.
+    pub legal_basis: Option<LegalBasis>,
+
+    pub status: AccessRequestStatus,
+
     pub created_at: u64,
+
+    pub decided_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct NewAccessRequest {
+    #[serde(rename = "badgeID")]
+    pub badge_id: u128,
+
+    #[serde(rename = "claimKeys")]
+    pub claim_keys: Vec<String>,
+
+    pub purpose: String,
+
+    pub message: Option<String>,
+
+    #[serde(rename = "legalBasis")]
+    pub legal_basis: LegalBasis,
+}
+
+/// LegalBasis is the ground on which a company processes the requested claims,
+/// following the lawful bases for processing of the GDPR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum LegalBasis {
+    #[serde(rename = "consent")]
+    Consent,
+
+    #[serde(rename = "contract")]
+    Contract,
+
+    #[serde(rename = "legalObligation")]
+    LegalObligation,
+
+    #[serde(rename = "vitalInterests")]
+    VitalInterests,
+
+    #[serde(rename = "publicTask")]
+    PublicTask,
+
+    #[serde(rename = "legitimateInterests")]
+    LegitimateInterests,
+}
+
+impl StoredAccessRequest {
+    pub fn includes_claim(&self, key: &str) -> bool {
+        match &self.claim_keys {
+            Some(keys) => keys.iter().any(|k| k == key),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum AccessRequestStatus {
+    #[serde(rename = "pending")]
+    Pending,
+
+    #[serde(rename = "approved")]
+    Approved,
+
+    #[serde(rename = "denied")]
+    Denied,
+
+    #[serde(rename = "withdrawn")]
+    Withdrawn,
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -185,8 +994,80 @@ pub struct AccessRequest {
 
     pub badge: Badge,
 
+    #[serde(rename = "claimKeys")]
+    pub claim_keys: Option<Vec<String>>,
+
+    pub purpose: Option<String>,
+
+    pub message: Option<String>,
+
+    #[serde(rename = "legalBasis")]
+    pub legal_basis: Option<LegalBasis>,
+
+    pub status: AccessRequestStatus,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    #[serde(rename = "decidedAt")]
+    pub decided_at: Option<u64>,
+}
+
+/// SentAccessRequest is an access request as seen by the company that sent it.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct SentAccessRequest {
+    #[serde(rename = "accessRequestID")]
+    pub id: u128,
+
+    pub badge: BadgeSummary,
+
+    #[serde(rename = "claimKeys")]
+    pub claim_keys: Option<Vec<String>>,
+
+    pub purpose: Option<String>,
+
+    #[serde(rename = "legalBasis")]
+    pub legal_basis: Option<LegalBasis>,
+
+    pub status: AccessRequestStatus,
+
     #[serde(rename = "createdAt")]
     pub created_at: u64,
+
+    #[serde(rename = "decidedAt")]
+    pub decided_at: Option<u64>,
+}
+
+/// AccessGrant gives a company access to the claims of a badge.
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct AccessGrant {
+    #[serde(rename = "principalID")]
+    pub principal_id: String,
+
+    #[serde(rename = "grantedAt")]
+    pub granted_at: u64,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<u64>,
+
+    /// The keys of the shared claims, or `None` if all claims are shared.
+    #[serde(rename = "claimKeys")]
+    pub claim_keys: Option<Vec<String>>,
+}
+
+impl AccessGrant {
+    /// Checks if the grant still gives access at the given time.
+    pub fn is_valid(&self, now: u64) -> bool {
+        !matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+    }
+
+    /// Checks if the claim with the given key is shared by the grant.
+    pub fn allows(&self, key: &str) -> bool {
+        match &self.claim_keys {
+            Some(keys) => keys.iter().any(|k| k == key),
+            None => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
@@ -197,13 +1078,128 @@ pub struct Role {
     pub name: String,
 }
 
-#[derive(CandidType, Serialize, Deserialize)]
-pub struct StableData {
-    pub principals: UsersMap,
+impl Role {
+    /// Checks if the role has to be approved by an administrator before it is granted.
+    /// Companies can read the email of every user and request access to every badge,
+    /// so their role is as privileged as those of lecturers and administrators.
+    pub fn is_privileged(&self) -> bool {
+        self.id == ADMINISTRATOR_ROLE_ID
+            || self.id == LECTURER_ROLE_ID
+            || self.id == COMPANY_ROLE_ID
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum RoleRequestStatus {
+    #[serde(rename = "pending")]
+    Pending,
 
-    pub badges: BadgesMap,
+    #[serde(rename = "approved")]
+    Approved,
 
-    pub access_requests: AccessRequestsMap,
+    #[serde(rename = "rejected")]
+    Rejected,
+}
 
-    pub badge_access_approvals: BadgeAccessApprovalsMap,
+#[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
+pub struct RoleRequest {
+    #[serde(rename = "principalID")]
+    pub principal_id: String,
+
+    #[serde(rename = "organisationID")]
+    pub organisation_id: u128,
+
+    pub roles: Vec<Role>,
+
+    pub status: RoleRequestStatus,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+
+    #[serde(rename = "decidedBy")]
+    pub decided_by: Option<String>,
+
+    #[serde(rename = "decidedAt")]
+    pub decided_at: Option<u64>,
+}
+
+/// IdKind identifies an entity kind with its own monotonically increasing ID sequence.
+/// The discriminants are part of the stable memory layout and must not change.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, CandidType, Serialize, Deserialize,
+)]
+pub enum IdKind {
+    Badge = 0,
+
+    AccessRequest = 1,
+
+    Organisation = 2,
+}
+
+/// InitArgs are the settings passed when the canister is installed or upgraded.
+/// Settings left out on upgrade keep their previous value.
+#[derive(Debug, Clone, Default, CandidType, Serialize, Deserialize)]
+pub struct InitArgs {
+    /// The principal allowed to register as the first administrator.
+    pub administrator: Option<Principal>,
+
+    /// The URL of the HTTP gateway serving the verification pages, see `Config::gateway_url`.
+    #[serde(rename = "gatewayUrl")]
+    pub gateway_url: Option<String>,
+
+    /// The name of the threshold ECDSA key badges are signed with, see `Config::ecdsa_key_name`.
+    #[serde(rename = "ecdsaKeyName")]
+    pub ecdsa_key_name: Option<String>,
+}
+
+/// Config holds the settings of the canister, as last passed in `InitArgs`.
+#[derive(Debug, Clone, Default, CandidType, Serialize, Deserialize)]
+pub struct Config {
+    pub administrator: Option<Principal>,
+
+    /// The URL of the HTTP gateway, where `{canisterId}` stands for the ID of the canister,
+    /// like `http://{canisterId}.raw.localhost:4943` for a local replica.
+    /// Defaults to the raw domain of the mainnet gateway.
+    pub gateway_url: Option<String>,
+
+    /// The name of the threshold ECDSA key, like `dfx_test_key` for a local replica.
+    /// Defaults to `key_1`, the production key on mainnet.
+    pub ecdsa_key_name: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BadgeStatus::{self, Active, Expired, Revoked, Suspended};
+
+    #[test]
+    fn badge_status_transitions() {
+        let transitions: [(BadgeStatus, BadgeStatus, bool); 16] = [
+            (Active, Active, false),
+            (Active, Suspended, true),
+            (Active, Revoked, true),
+            (Active, Expired, true),
+            (Suspended, Active, true),
+            (Suspended, Suspended, false),
+            (Suspended, Revoked, true),
+            (Suspended, Expired, true),
+            (Revoked, Active, false),
+            (Revoked, Suspended, false),
+            (Revoked, Revoked, false),
+            (Revoked, Expired, false),
+            (Expired, Active, false),
+            (Expired, Suspended, false),
+            (Expired, Revoked, false),
+            (Expired, Expired, false),
+        ];
+
+        for (from, to, allowed) in transitions {
+            assert_eq!(
+                from.can_transition_to(to),
+                allowed,
+                "{:?} -> {:?}",
+                from,
+                to
+            );
+        }
+    }
 }
@@ -0,0 +1,273 @@
+use serde_json::{json, Value};
+
+use crate::http;
+use crate::model::{Alignment, Badge, BadgeSource, Claim, Error, Provenance};
+use crate::util::{format_timestamp, parse_timestamp};
+
+const CREDENTIALS_V2_CONTEXT: &str = "https://www.w3.org/ns/credentials/v2";
+const OPEN_BADGES_V3_CONTEXT: &str = "https://purl.imsglobal.org/spec/ob/v3p0/context-3.0.3.json";
+
+/// ImportedCredential holds the fields of an Open Badges credential that a badge is created from.
+pub struct ImportedCredential {
+    pub title: String,
+
+    pub description: Option<String>,
+
+    pub issued_at: u64,
+
+    pub expires_at: Option<u64>,
+
+    /// The results of the credential subject, keyed by the name of their result description.
+    pub claims: Vec<Claim>,
+
+    pub provenance: Provenance,
+}
+
+/// to_open_badge renders the badge as an Open Badges 3.0 `OpenBadgeCredential`.
+/// The organisation holding the badge is the issuer of the exported credential. Imported badges keep
+/// the achievement criteria and alignments of the original credential and name its issuer as creator.
+/// The claims left on the badge become the results of the credential subject, each described by a
+/// result description of the achievement named after the claim key.
+/// Like the verifiable credential export, it carries the proof of the canister signature, if any.
+pub fn to_open_badge(badge: &Badge, proof: Option<Value>) -> Value {
+    let url = http::badge_url(badge.id);
+
+    let mut issuer = json!({
+        "id": format!("urn:badger:organisation:{}", badge.issuer.id),
+        "type": ["Profile"],
+        "name": badge.issuer.name,
+    });
+    if let Some(website) = &badge.issuer.website {
+        issuer["url"] = json!(website);
+    }
+
+    let criteria = badge
+        .provenance
+        .as_ref()
+        .and_then(|provenance| provenance.criteria.clone())
+        .or_else(|| badge.description.clone())
+        .unwrap_or_else(|| badge.title.clone());
+
+    let mut achievement = json!({
+        "id": format!("{}#achievement", url),
+        "type": ["Achievement"],
+        "name": badge.title,
+        "description": badge.description.clone().unwrap_or_default(),
+        "criteria": { "narrative": criteria },
+    });
+
+    if !badge.claims.is_empty() {
+        let descriptions: Vec<Value> = badge
+            .claims
+            .iter()
+            .enumerate()
+            .map(|(index, claim)| {
+                json!({
+                    "id": result_description_id(&url, index),
+                    "type": ["ResultDescription"],
+                    "name": claim.key,
+                    "resultType": "Result",
+                })
+            })
+            .collect();
+        achievement["resultDescription"] = json!(descriptions);
+    }
+
+    if let Some(provenance) = &badge.provenance {
+        let mut creator = json!({
+            "type": ["Profile"],
+            "name": provenance.issuer_name,
+        });
+        if let Some(issuer_id) = &provenance.issuer_id {
+            creator["id"] = json!(issuer_id);
+        }
+        achievement["creator"] = creator;
+
+        let alignments: Vec<Value> = provenance
+            .alignments
+            .iter()
+            .map(|alignment| {
+                let mut value = json!({
+                    "type": ["Alignment"],
+                    "targetName": alignment.target_name,
+                    "targetUrl": alignment.target_url,
+                });
+                if let Some(framework) = &alignment.target_framework {
+                    value["targetFramework"] = json!(framework);
+                }
+                value
+            })
+            .collect();
+        if !alignments.is_empty() {
+            achievement["alignment"] = json!(alignments);
+        }
+    }
+
+    let mut credential = json!({
+        "@context": [CREDENTIALS_V2_CONTEXT, OPEN_BADGES_V3_CONTEXT],
+        "id": url,
+        "type": ["VerifiableCredential", "OpenBadgeCredential"],
+        "name": badge.title,
+        "issuer": issuer,
+        "validFrom": format_timestamp(badge.created_at),
+        "credentialSubject": {
+            "id": format!("did:icp:{}", badge.owner.principal_id),
+            "type": ["AchievementSubject"],
+            "achievement": achievement,
+        },
+        "credentialStatus": {
+            "id": format!("{}.json", url),
+            "type": "BadgerBadgeStatus",
+            "status": badge.status,
+        },
+    });
+
+    if !badge.claims.is_empty() {
+        let results: Vec<Value> = badge
+            .claims
+            .iter()
+            .enumerate()
+            .map(|(index, claim)| {
+                json!({
+                    "type": ["Result"],
+                    "resultDescription": result_description_id(&url, index),
+                    "value": claim.value,
+                })
+            })
+            .collect();
+        credential["credentialSubject"]["result"] = json!(results);
+    }
+
+    if let Some(expires_at) = badge.expires_at {
+        credential["validUntil"] = json!(format_timestamp(expires_at));
+    }
+
+    if let Some(proof) = proof {
+        credential["proof"] = proof;
+    }
+
+    credential
+}
+
+/// from_open_badge reads an Open Badges 3.0 credential.
+/// Both the VC 2.0 `validFrom` and the VC 1.1 `issuanceDate` are accepted as the issue date.
+pub fn from_open_badge(
+    credential: &str,
+    imported_by: String,
+    imported_at: u64,
+) -> Result<ImportedCredential, Error> {
+    let invalid = |message: &str| Error::invalid_input("credential", message);
+
+    let credential: Value = serde_json::from_str(credential)
+        .map_err(|err| invalid(&format!("Invalid JSON: {}", err)))?;
+
+    let is_open_badge = credential["type"].as_array().is_some_and(|types| {
+        types.iter().any(|t| {
+            t.as_str() == Some("OpenBadgeCredential") || t.as_str() == Some("AchievementCredential")
+        })
+    });
+    if !is_open_badge {
+        return Err(invalid("Credential is not an OpenBadgeCredential."));
+    }
+
+    let achievement = &credential["credentialSubject"]["achievement"];
+    let title = achievement["name"]
+        .as_str()
+        .filter(|name| !name.trim().is_empty())
+        .ok_or_else(|| invalid("Achievement has no name."))?;
+
+    // The issuer is either a profile or the URI of one.
+    let issuer = &credential["issuer"];
+    let issuer_id = issuer["id"].as_str().or(issuer.as_str());
+    let issuer_name = issuer["name"]
+        .as_str()
+        .or(issuer_id)
+        .ok_or_else(|| invalid("Credential has no issuer."))?;
+
+    let issued_at = credential["validFrom"]
+        .as_str()
+        .or(credential["issuanceDate"].as_str())
+        .ok_or_else(|| invalid("Credential has no issue date."))?;
+    let issued_at = parse_timestamp(issued_at)
+        .ok_or_else(|| invalid(&format!("Invalid issue date: {}", issued_at)))?;
+
+    let expires_at = match credential["validUntil"]
+        .as_str()
+        .or(credential["expirationDate"].as_str())
+    {
+        Some(value) => Some(
+            parse_timestamp(value)
+                .ok_or_else(|| invalid(&format!("Invalid expiry date: {}", value)))?,
+        ),
+        None => None,
+    };
+
+    let alignments = achievement["alignment"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|alignment| {
+            Some(Alignment {
+                target_name: alignment["targetName"].as_str()?.to_string(),
+                target_url: alignment["targetUrl"].as_str()?.to_string(),
+                target_framework: alignment["targetFramework"].as_str().map(String::from),
+            })
+        })
+        .collect();
+
+    // Results are keyed by the name of the result description they refer to.
+    // Results without a value or a named description cannot be stored as claims and are left out.
+    let mut claims: Vec<Claim> = Vec::new();
+    for result in credential["credentialSubject"]["result"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let value = match &result["value"] {
+            Value::String(value) => value.clone(),
+            Value::Number(value) => value.to_string(),
+            _ => continue,
+        };
+        let key = achievement["resultDescription"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|description| {
+                description["id"].is_string() && description["id"] == result["resultDescription"]
+            })
+            .and_then(|description| description["name"].as_str());
+        match key {
+            Some(key) if !claims.iter().any(|claim| claim.key == key) => claims.push(Claim {
+                key: key.to_string(),
+                value,
+            }),
+            _ => continue,
+        }
+    }
+
+    Ok(ImportedCredential {
+        title: title.to_string(),
+        description: achievement["description"].as_str().map(String::from),
+        issued_at,
+        expires_at,
+        claims,
+        provenance: Provenance {
+            source: BadgeSource::OpenBadges,
+            credential_id: credential["id"].as_str().map(String::from),
+            issuer_id: issuer_id.map(String::from),
+            issuer_name: issuer_name.to_string(),
+            criteria: achievement["criteria"]["narrative"]
+                .as_str()
+                .map(String::from),
+            alignments,
+            imported_by,
+            imported_at,
+        },
+    })
+}
+
+/// result_description_id returns the ID of the result description of the claim at the given index,
+/// since claim keys are not necessarily valid in a URL.
+fn result_description_id(url: &str, index: usize) -> String {
+    format!("{}#result-{}", url, index)
+}
@@ -0,0 +1,245 @@
+use candid::{CandidType, Decode, Encode};
+use serde::Deserialize;
+
+use crate::model::{
+    BadgeSortBy, Error, Page, PageRequest, SortOrder, StoredBadge, User, UserSortBy,
+};
+use crate::util::authenticated_user;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// SortKey is a value an entity is sorted by.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, CandidType, Deserialize)]
+pub enum SortKey {
+    Number(u128),
+    Text(String),
+}
+
+/// Cursor is the position of an entity in a sorted result set.
+/// The ID breaks ties between entities with the same sort key, so that every position is unique.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, CandidType, Deserialize)]
+pub struct Cursor {
+    pub key: SortKey,
+
+    pub id: SortKey,
+}
+
+impl Cursor {
+    /// encode returns the cursor as an opaque string that can be handed to clients.
+    pub fn encode(&self) -> String {
+        Encode!(self)
+            .expect("Could not encode cursor.")
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// decode parses a cursor previously returned by `encode`.
+    pub fn decode(value: &str) -> Result<Cursor, Error> {
+        let invalid = || Error::invalid_input("cursor", format!("Invalid cursor: {}", value));
+
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|i| {
+                value
+                    .get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+
+        Decode!(&bytes, Cursor).map_err(|_| invalid())
+    }
+}
+
+/// paginate sorts the items by their cursor and returns the page following the requested cursor.
+/// Since the cursor holds the sort key of the last returned item,
+/// pages stay consistent when items are inserted or removed between requests.
+pub fn paginate<T>(
+    mut items: Vec<(Cursor, T)>,
+    order: SortOrder,
+    page: Option<PageRequest>,
+) -> Result<Page<T>, Error> {
+    let page = page.unwrap_or_default();
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(Error::invalid_input(
+            "limit",
+            format!("Page size must be between 1 and {}.", MAX_PAGE_SIZE),
+        ));
+    }
+
+    let after = match page.cursor {
+        Some(cursor) => Some(Cursor::decode(&cursor)?),
+        None => None,
+    };
+
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    if order == SortOrder::Desc {
+        items.reverse();
+    }
+
+    let total = items.len() as u64;
+
+    let mut remaining: Vec<(Cursor, T)> = items
+        .into_iter()
+        .filter(|(cursor, _)| match &after {
+            Some(after) if order == SortOrder::Asc => cursor > after,
+            Some(after) => cursor < after,
+            None => true,
+        })
+        .collect();
+
+    let has_more = remaining.len() > limit as usize;
+    remaining.truncate(limit as usize);
+
+    let next_cursor = if has_more {
+        remaining.last().map(|(cursor, _)| cursor.encode())
+    } else {
+        None
+    };
+
+    Ok(Page {
+        items: remaining.into_iter().map(|(_, item)| item).collect(),
+        next_cursor,
+        total,
+    })
+}
+
+/// badge_cursor returns the position of the badge when sorted by the given field.
+pub fn badge_cursor(badge: &StoredBadge, sort_by: BadgeSortBy) -> Cursor {
+    let key = match sort_by {
+        BadgeSortBy::CreatedAt => SortKey::Number(badge.created_at as u128),
+        BadgeSortBy::Title => SortKey::Text(badge.title.to_lowercase()),
+        BadgeSortBy::OwnerName => SortKey::Text(
+            authenticated_user(badge.owner_principal)
+                .map(|owner| owner.name.to_lowercase())
+                .unwrap_or_default(),
+        ),
+    };
+
+    Cursor {
+        key,
+        id: SortKey::Number(badge.id),
+    }
+}
+
+/// user_cursor returns the position of the user when sorted by the given field.
+pub fn user_cursor(user: &User, sort_by: UserSortBy) -> Cursor {
+    let key = match sort_by {
+        UserSortBy::CreatedAt => SortKey::Number(user.created_at as u128),
+        UserSortBy::Name => SortKey::Text(user.name.to_lowercase()),
+    };
+
+    Cursor {
+        key,
+        id: SortKey::Text(user.principal_id.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{paginate, Cursor, SortKey, MAX_PAGE_SIZE};
+    use crate::model::{Error, PageRequest, SortOrder};
+
+    fn items(count: u128) -> Vec<(Cursor, u128)> {
+        (1..=count)
+            .map(|id| {
+                let cursor = Cursor {
+                    key: SortKey::Text(format!("item {}", id % 3)),
+                    id: SortKey::Number(id),
+                };
+                (cursor, id)
+            })
+            .collect()
+    }
+
+    fn request(cursor: Option<String>, limit: u32) -> Option<PageRequest> {
+        Some(PageRequest {
+            cursor,
+            limit: Some(limit),
+        })
+    }
+
+    fn assert_invalid_input(result: Result<impl std::fmt::Debug, Error>, expected: &str) {
+        match result {
+            Err(Error::InvalidInput { field, .. }) => assert_eq!(field, expected),
+            other => panic!("Expected invalid {}, got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn returns_an_empty_page_without_items() {
+        let page = paginate(items(0), SortOrder::Asc, None).unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_cursor, None);
+        assert_eq!(page.total, 0);
+    }
+
+    #[test]
+    fn follows_the_cursor_to_the_last_page() {
+        let first = paginate(items(5), SortOrder::Asc, request(None, 2)).unwrap();
+        assert_eq!(first.items, vec![3, 1]);
+        assert_eq!(first.total, 5);
+        assert!(first.next_cursor.is_some());
+
+        let last = paginate(items(5), SortOrder::Asc, request(first.next_cursor, 3)).unwrap();
+        assert_eq!(last.items, vec![4, 2, 5]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn follows_the_cursor_in_descending_order() {
+        let first = paginate(items(5), SortOrder::Desc, request(None, 3)).unwrap();
+        assert_eq!(first.items, vec![5, 2, 4]);
+
+        let last = paginate(items(5), SortOrder::Desc, request(first.next_cursor, 3)).unwrap();
+        assert_eq!(last.items, vec![1, 3]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn has_no_next_cursor_when_the_last_page_is_full() {
+        let page = paginate(items(4), SortOrder::Asc, request(None, 4)).unwrap();
+        assert_eq!(page.items.len(), 4);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        for cursor in ["zz", "abc", "deadbeef", "\u{e9}\u{e9}", "4449444c"] {
+            assert_invalid_input(
+                paginate(
+                    items(3),
+                    SortOrder::Asc,
+                    request(Some(cursor.to_string()), 2),
+                ),
+                "cursor",
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_encoded_cursors() {
+        let cursor = Cursor {
+            key: SortKey::Number(42),
+            id: SortKey::Text("abc".to_string()),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    }
+
+    #[test]
+    fn rejects_limits_out_of_range() {
+        assert_invalid_input(
+            paginate(items(3), SortOrder::Asc, request(None, MAX_PAGE_SIZE + 1)),
+            "limit",
+        );
+        assert_invalid_input(
+            paginate(items(3), SortOrder::Asc, request(None, 0)),
+            "limit",
+        );
+        assert!(paginate(items(3), SortOrder::Asc, request(None, MAX_PAGE_SIZE)).is_ok());
+    }
+}
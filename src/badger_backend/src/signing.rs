@@ -0,0 +1,490 @@
+use std::cell::RefCell;
+use std::time::Duration;
+
+use candid::{Encode, Principal};
+use ic_cdk::api::id;
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::time;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::model::{
+    BadgeSignature, Claim, ClaimDigest, Error, SignedBadgeContent, SigningPublicKey, StoredBadge,
+};
+use crate::util::{format_timestamp, to_hex};
+
+/// The signature algorithm of all signers.
+pub const ALGORITHM: &str = "ecdsa-secp256k1-sha256";
+
+/// The delay before loading the public key is retried, doubled after every failure.
+const LOAD_PUBLIC_KEY_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The longest delay between two attempts to load the public key.
+const LOAD_PUBLIC_KEY_MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+thread_local! {
+    /// The public key of the signer, fetched after every install and upgrade,
+    /// since queries cannot call the management canister.
+    static PUBLIC_KEY: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// Signer creates the canister signatures of badges.
+/// Signatures are ECDSA signatures over secp256k1, encoded as the concatenation of r and s.
+#[allow(async_fn_in_trait)]
+pub trait Signer {
+    fn key_name(&self) -> String;
+
+    /// public_key returns the public key in SEC1 compressed form.
+    async fn public_key(&self) -> Result<Vec<u8>, Error>;
+
+    async fn sign(&self, message_hash: [u8; 32]) -> Result<Vec<u8>, Error>;
+}
+
+/// NonceSource provides the nonces the claims of a badge are salted with.
+#[allow(async_fn_in_trait)]
+pub trait NonceSource {
+    async fn nonce(&self) -> Result<Vec<u8>, Error>;
+}
+
+/// RawRand draws nonces from the randomness of the management canister.
+pub struct RawRand;
+
+impl NonceSource for RawRand {
+    async fn nonce(&self) -> Result<Vec<u8>, Error> {
+        raw_rand()
+            .await
+            .map(|(nonce,)| nonce)
+            .map_err(|(_, message)| Error::unavailable(format!("Could not get nonce: {}", message)))
+    }
+}
+
+#[cfg(not(feature = "local-signer"))]
+pub use threshold::signer;
+
+#[cfg(feature = "local-signer")]
+pub use local::signer;
+
+#[cfg(not(feature = "local-signer"))]
+mod threshold {
+    use ic_cdk::api::call::call_with_payment128;
+    use ic_cdk::api::management_canister::ecdsa::{
+        ecdsa_public_key, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument, SignWithEcdsaArgument,
+        SignWithEcdsaResponse,
+    };
+    use ic_cdk::api::management_canister::main::CanisterId;
+
+    use super::Signer;
+    use crate::config;
+    use crate::model::Error;
+
+    /// The name of the threshold ECDSA key on mainnet, used unless `ecdsaKeyName` is configured.
+    const DEFAULT_KEY_NAME: &str = "key_1";
+
+    /// The cycles attached to `sign_with_ecdsa`, unused cycles are refunded.
+    const SIGN_WITH_ECDSA_CYCLES: u128 = 26_153_846_153;
+
+    /// ThresholdSigner signs with a threshold ECDSA key held by the subnet,
+    /// so the private key is never available to the canister.
+    pub struct ThresholdSigner {
+        key_name: String,
+    }
+
+    impl ThresholdSigner {
+        fn key_id(&self) -> EcdsaKeyId {
+            EcdsaKeyId {
+                curve: EcdsaCurve::Secp256k1,
+                name: self.key_name.clone(),
+            }
+        }
+    }
+
+    impl Signer for ThresholdSigner {
+        fn key_name(&self) -> String {
+            self.key_name.clone()
+        }
+
+        async fn public_key(&self) -> Result<Vec<u8>, Error> {
+            let argument = EcdsaPublicKeyArgument {
+                canister_id: None::<CanisterId>,
+                derivation_path: Vec::new(),
+                key_id: self.key_id(),
+            };
+            ecdsa_public_key(argument)
+                .await
+                .map(|(response,)| response.public_key)
+                .map_err(|(_, message)| {
+                    Error::unavailable(format!("Could not get public key: {}", message))
+                })
+        }
+
+        async fn sign(&self, message_hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+            let argument = SignWithEcdsaArgument {
+                message_hash: message_hash.to_vec(),
+                derivation_path: Vec::new(),
+                key_id: self.key_id(),
+            };
+            let result: Result<(SignWithEcdsaResponse,), _> = call_with_payment128(
+                CanisterId::management_canister(),
+                "sign_with_ecdsa",
+                (argument,),
+                SIGN_WITH_ECDSA_CYCLES,
+            )
+            .await;
+            result
+                .map(|(response,)| response.signature)
+                .map_err(|(_, message)| {
+                    Error::unavailable(format!("Could not sign badge: {}", message))
+                })
+        }
+    }
+
+    pub fn signer() -> ThresholdSigner {
+        ThresholdSigner {
+            key_name: config::get()
+                .ecdsa_key_name
+                .unwrap_or_else(|| DEFAULT_KEY_NAME.to_string()),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "local-signer"))]
+mod local {
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::{Signature, SigningKey};
+    use sha2::{Digest, Sha256};
+
+    use super::Signer;
+    use crate::model::Error;
+
+    /// LocalSigner signs with a key derived from a fixed seed, so signatures are reproducible.
+    /// It must only be used in tests, since anyone can derive the private key.
+    pub struct LocalSigner {
+        key: SigningKey,
+    }
+
+    impl Signer for LocalSigner {
+        fn key_name(&self) -> String {
+            "local".to_string()
+        }
+
+        async fn public_key(&self) -> Result<Vec<u8>, Error> {
+            Ok(self
+                .key
+                .verifying_key()
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec())
+        }
+
+        async fn sign(&self, message_hash: [u8; 32]) -> Result<Vec<u8>, Error> {
+            let signature: Signature = self
+                .key
+                .sign_prehash(&message_hash)
+                .map_err(|err| Error::unavailable(format!("Could not sign badge: {}", err)))?;
+            Ok(signature.to_bytes().to_vec())
+        }
+    }
+
+    pub fn signer() -> LocalSigner {
+        let seed = Sha256::digest(b"badger local signing key");
+        LocalSigner {
+            key: SigningKey::from_bytes(&seed).expect("Invalid local signing key."),
+        }
+    }
+}
+
+/// schedule_load_public_key loads the public key of the signer after the given delay.
+/// Failed attempts are logged and retried with a doubled delay, until the key is loaded.
+pub fn schedule_load_public_key(delay: Duration) {
+    ic_cdk_timers::set_timer(delay, move || {
+        ic_cdk::spawn(async move {
+            if let Err(err) = load_public_key().await {
+                let retry_delay =
+                    (delay * 2).clamp(LOAD_PUBLIC_KEY_RETRY_DELAY, LOAD_PUBLIC_KEY_MAX_RETRY_DELAY);
+                ic_cdk::println!(
+                    "Could not load the signing public key, retrying in {} seconds: {:?}",
+                    retry_delay.as_secs(),
+                    err
+                );
+                schedule_load_public_key(retry_delay);
+            }
+        })
+    });
+}
+
+/// load_public_key fetches the public key of the signer into the cache.
+async fn load_public_key() -> Result<(), Error> {
+    let key = signer().public_key().await?;
+    PUBLIC_KEY.with(|it| *it.borrow_mut() = Some(key));
+    Ok(())
+}
+
+/// public_key returns the cached public key of the signer, if it has been loaded yet.
+pub fn public_key() -> Option<SigningPublicKey> {
+    PUBLIC_KEY
+        .with(|it| it.borrow().clone())
+        .map(|public_key| SigningPublicKey {
+            algorithm: ALGORITHM.to_string(),
+            key_name: signer().key_name(),
+            public_key,
+        })
+}
+
+/// sign_badge signs the canonical serialisation of the badge with a fresh nonce
+/// of the management canister.
+pub async fn sign_badge(badge: &StoredBadge) -> Result<BadgeSignature, Error> {
+    let signature = sign_badge_with(&signer(), &RawRand, id(), badge, time()).await?;
+
+    if PUBLIC_KEY.with(|it| it.borrow().is_none()) {
+        if let Err(err) = load_public_key().await {
+            ic_cdk::println!("Could not load the signing public key: {:?}", err);
+        }
+    }
+
+    Ok(signature)
+}
+
+/// sign_badge_with signs the canonical serialisation of the badge issued by the given canister,
+/// salting its claims with a nonce of the given source.
+pub async fn sign_badge_with(
+    signer: &impl Signer,
+    nonces: &impl NonceSource,
+    canister_id: Principal,
+    badge: &StoredBadge,
+    signed_at: u64,
+) -> Result<BadgeSignature, Error> {
+    let nonce = nonces.nonce().await?;
+    let signature = signer
+        .sign(message_hash(canister_id, badge, &nonce))
+        .await?;
+
+    Ok(BadgeSignature {
+        nonce,
+        signature,
+        key_name: signer.key_name(),
+        signed_at,
+    })
+}
+
+/// proof returns the proof of a credential exported from the badge issued by the given canister,
+/// disclosing the salts of the given claims only, usually those left on the masked badge.
+/// Badges without a canister signature have no proof.
+pub fn proof(canister_id: Principal, stored: &StoredBadge, disclosed: &[Claim]) -> Option<Value> {
+    let signature = stored.signature.as_ref()?;
+    let content = Encode!(&signed_content(canister_id, stored, &signature.nonce))
+        .expect("Could not encode badge content.");
+
+    let disclosures: Vec<Value> = disclosed
+        .iter()
+        .map(|claim| {
+            json!({
+                "key": claim.key,
+                "salt": to_hex(&claim_salt(&signature.nonce, &claim.key)),
+            })
+        })
+        .collect();
+
+    Some(json!({
+        "type": "BadgerCanisterSignature",
+        "algorithm": ALGORITHM,
+        "verificationMethod": format!("ic:{}#signing_public_key", canister_id),
+        "keyName": signature.key_name,
+        "created": format_timestamp(signature.signed_at),
+        "signedContent": to_hex(&content),
+        "disclosures": disclosures,
+        "proofValue": to_hex(&signature.signature),
+    }))
+}
+
+fn message_hash(canister_id: Principal, badge: &StoredBadge, nonce: &[u8]) -> [u8; 32] {
+    let content = Encode!(&signed_content(canister_id, badge, nonce))
+        .expect("Could not encode badge content.");
+    Sha256::digest(content).into()
+}
+
+fn signed_content(canister_id: Principal, badge: &StoredBadge, nonce: &[u8]) -> SignedBadgeContent {
+    SignedBadgeContent {
+        canister_id,
+        id: badge.id,
+        title: badge.title.clone(),
+        description: badge.description.clone(),
+        badge_type: badge.badge_type,
+        issuer_id: badge.issuer_id,
+        owner_principal: badge.owner_principal,
+        claims: badge
+            .claims
+            .iter()
+            .map(|claim| ClaimDigest {
+                key: claim.key.clone(),
+                digest: Sha256::new()
+                    .chain_update(claim_salt(nonce, &claim.key))
+                    .chain_update(claim.value.as_bytes())
+                    .finalize()
+                    .to_vec(),
+            })
+            .collect(),
+        created_at: badge.created_at,
+        expires_at: badge.expires_at,
+    }
+}
+
+/// claim_salt derives the salt of a claim from the nonce of the badge signature.
+fn claim_salt(nonce: &[u8], key: &str) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(nonce)
+        .chain_update(key.as_bytes())
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use candid::{Decode, Principal};
+    use k256::ecdsa::signature::hazmat::PrehashVerifier;
+    use k256::ecdsa::{Signature, VerifyingKey};
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+
+    use super::{local, proof, sign_badge_with, NonceSource, Signer};
+    use crate::model::{BadgeStatus, Claim, Error, SignedBadgeContent, StoredBadge};
+
+    /// FixedNonce returns the same nonce for every badge, so signatures are reproducible.
+    struct FixedNonce(Vec<u8>);
+
+    impl NonceSource for FixedNonce {
+        async fn nonce(&self) -> Result<Vec<u8>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    /// block_on polls a future that completes without waiting, like those of the local signer.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("Future is pending."),
+        }
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn canister_id() -> Principal {
+        Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 1, 1])
+    }
+
+    fn claim(key: &str, value: &str) -> Claim {
+        Claim {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn signed_badge() -> StoredBadge {
+        let mut badge = StoredBadge {
+            id: 7,
+            title: "Safety training".to_string(),
+            description: Some("Completed the yearly safety training.".to_string()),
+            badge_type: 1,
+            issuer_id: 3,
+            owner_principal: Principal::from_slice(&[42]),
+            status: BadgeStatus::Active,
+            revocation: None,
+            claims: vec![claim("grade", "A"), claim("hours", "16")],
+            signed_by: Vec::new(),
+            created_at: 1_700_000_000_000_000_000,
+            expires_at: None,
+            provenance: None,
+            signature: None,
+        };
+        let signature = block_on(sign_badge_with(
+            &local::signer(),
+            &FixedNonce(vec![9; 32]),
+            canister_id(),
+            &badge,
+            1_700_000_001_000_000_000,
+        ))
+        .unwrap();
+        badge.signature = Some(signature);
+        badge
+    }
+
+    /// verify checks the signature of the proof against the public key of the local signer
+    /// and returns the signed content.
+    fn verify(proof: &Value) -> Result<SignedBadgeContent, String> {
+        let content = from_hex(proof["signedContent"].as_str().unwrap());
+        let signature = Signature::from_slice(&from_hex(proof["proofValue"].as_str().unwrap()))
+            .map_err(|err| err.to_string())?;
+        let public_key = block_on(local::signer().public_key()).unwrap();
+        VerifyingKey::from_sec1_bytes(&public_key)
+            .unwrap()
+            .verify_prehash(&Sha256::digest(&content), &signature)
+            .map_err(|err| err.to_string())?;
+        Ok(Decode!(&content, SignedBadgeContent).unwrap())
+    }
+
+    #[test]
+    fn proof_verifies_with_the_public_key_of_the_signer() {
+        let badge = signed_badge();
+        let proof = proof(canister_id(), &badge, &badge.claims).unwrap();
+
+        let content = verify(&proof).unwrap();
+        assert_eq!(content.canister_id, canister_id());
+        assert_eq!(content.id, badge.id);
+        assert_eq!(content.title, badge.title);
+        assert_eq!(content.owner_principal, badge.owner_principal);
+        assert_eq!(proof["keyName"], "local");
+    }
+
+    #[test]
+    fn disclosed_salts_match_the_signed_claim_digests() {
+        let badge = signed_badge();
+        let proof = proof(canister_id(), &badge, &badge.claims[..1]).unwrap();
+        let content = verify(&proof).unwrap();
+
+        let disclosures = proof["disclosures"].as_array().unwrap();
+        assert_eq!(disclosures.len(), 1);
+        assert_eq!(disclosures[0]["key"], "grade");
+
+        let salt = from_hex(disclosures[0]["salt"].as_str().unwrap());
+        let digest = Sha256::new()
+            .chain_update(&salt)
+            .chain_update(b"A")
+            .finalize()
+            .to_vec();
+        assert_eq!(content.claims[0].key, "grade");
+        assert_eq!(content.claims[0].digest, digest);
+        assert_eq!(content.claims.len(), 2);
+    }
+
+    #[test]
+    fn signatures_are_bound_to_the_nonce() {
+        let badge = signed_badge();
+        let signature = badge.signature.as_ref().unwrap();
+        assert_eq!(signature.nonce, vec![9; 32]);
+        assert_eq!(signature.signed_at, 1_700_000_001_000_000_000);
+
+        let mut tampered = badge.clone();
+        tampered.signature.as_mut().unwrap().nonce = vec![8; 32];
+        let proof = proof(canister_id(), &tampered, &tampered.claims).unwrap();
+        assert!(verify(&proof).is_err());
+    }
+
+    #[test]
+    fn unsigned_badges_have_no_proof() {
+        let mut badge = signed_badge();
+        badge.signature = None;
+        assert!(proof(canister_id(), &badge, &badge.claims).is_none());
+    }
+}
@@ -4,47 +4,397 @@ use candid::Principal;
 use ic_cdk::api::{caller, time};
 
 use crate::{
-    model::{Badge, Organisation, Role, User},
-    ADMINISTRATOR_ROLE_ID, BADGE_ACCESS_APPROVALS, COMPANY_ROLE_ID, LECTURER_ROLE_ID,
-    ORGANISATIONS, PRINCIPALS, ROLES, STUDENT_ROLE_ID,
+    certification, index,
+    memory::StorableVec,
+    model::{
+        AccessGrant, Badge, BadgeStatus, BadgeVerification, CertifiedBadgeState,
+        CertifiedBadgeVerification, Claim, Error, IdKind, Organisation, Role, RoleRequest,
+        StoredAccessRequest, StoredBadge, StoredUser, User,
+    },
+    ACCESS_REQUESTS, ADMINISTRATOR_ROLE_ID, BADGES, BADGE_ACCESS_APPROVALS, COMPANY_ROLE_ID,
+    ID_COUNTERS, LECTURER_ROLE_ID, ORGANISATIONS, PRINCIPALS, ROLES, STUDENT_ROLE_ID,
 };
 
-pub fn authenticated_caller() -> Principal {
+/// authenticated_caller returns the principal of the caller, or an error for anonymous callers.
+pub fn authenticated_caller() -> Result<Principal, Error> {
     let principal = caller();
     if principal == Principal::anonymous() {
-        panic!("Anonymous caller not allowed.");
+        return Err(Error::unauthorized(principal));
     }
-    principal
+    Ok(principal)
 }
 
 pub fn authenticated_user(p: Principal) -> Option<User> {
-    PRINCIPALS.with(|it| it.borrow().get(&p).cloned())
+    PRINCIPALS
+        .with(|it| it.borrow().get(&p.into()))
+        .map(|user| hydrate_user(&user))
 }
 
-/// clear_claims returns a copy of the badge with all claims removed if the user is a company.
+/// next_id allocates the next ID for the given entity kind.
+/// IDs are never reused, even if the entity they were allocated for is removed.
+pub fn next_id(kind: IdKind) -> u128 {
+    ID_COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        let id = counters.get(&kind).unwrap_or(0) + 1;
+        counters.insert(kind, id);
+        id
+    })
+}
+
+/// find_claim_grant returns the access grant of the principal for the badge if it has not expired.
+pub fn find_claim_grant(badge_id: u128, principal_id: &str) -> Option<AccessGrant> {
+    let now = time();
+    BADGE_ACCESS_APPROVALS.with(|it| {
+        it.borrow()
+            .get(&badge_id)?
+            .0
+            .into_iter()
+            .find(|grant| grant.principal_id == principal_id && grant.is_valid(now))
+    })
+}
+
+/// clear_claims returns a copy of the badge that only contains the claims a company has been granted access to.
+/// The keys of the removed claims are listed so that the company can request them.
+/// Grants end when the badge expires, even before the expiry job has removed them.
 pub fn clear_claims(user: &User, badge: &Badge) -> Badge {
     let mut result = badge.clone();
     if !user.is_company() {
         return result;
     }
 
-    let is_approved = BADGE_ACCESS_APPROVALS.with(|it| {
-        let it_ref = it.borrow();
-        let approved_for_badge = it_ref.get(&badge.id);
-        if approved_for_badge.is_none() {
-            return false;
+    let is_expired = badge.status == BadgeStatus::Expired
+        || badge
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= time());
+    let grant = if is_expired {
+        None
+    } else {
+        find_claim_grant(badge.id, &user.principal_id)
+    };
+    let (shared, withheld): (Vec<Claim>, Vec<Claim>) = badge
+        .claims
+        .iter()
+        .cloned()
+        .partition(|claim| matches!(&grant, Some(grant) if grant.allows(&claim.key)));
+    result.claims = shared;
+    result.withheld_claim_keys = withheld.into_iter().map(|claim| claim.key).collect();
+    result
+}
+
+/// find_roles returns the roles with the given IDs, or an error naming the first unknown role.
+pub fn find_roles(role_ids: &[u128]) -> Result<Vec<Role>, Error> {
+    ROLES.with(|roles_map| {
+        let roles_map = roles_map.borrow();
+        let mut roles = Vec::new();
+        for role_id in role_ids {
+            match roles_map.get(role_id) {
+                Some(role) => roles.push(role),
+                None => return Err(Error::not_found("role", role_id)),
+            }
         }
-        let approved_for_badge = approved_for_badge.unwrap();
-        let requeting_principal = Principal::from_str(&user.principal_id).unwrap();
-        approved_for_badge.contains(&requeting_principal)
+        Ok(roles)
+    })
+}
+
+/// save_user stores the user under their principal, with their organisation by reference.
+pub fn save_user(user: &User) {
+    let p = Principal::from_str(&user.principal_id).unwrap();
+    PRINCIPALS.with(|principals| {
+        principals
+            .borrow_mut()
+            .insert(p.into(), StoredUser::from(user))
+    });
+}
+
+/// hydrate_user resolves the organisation of the stored user.
+/// Organisations are never removed, so it is expected to exist.
+pub fn hydrate_user(user: &StoredUser) -> User {
+    let organisation = ORGANISATIONS
+        .with(|orgs| orgs.borrow().get(&user.organisation_id))
+        .unwrap_or_else(|| {
+            panic!(
+                "Organisation {} of user with principal {} not found.",
+                user.organisation_id, user.principal_id
+            )
+        });
+
+    User {
+        name: user.name.clone(),
+        email: user.email.clone(),
+        principal_id: user.principal_id.clone(),
+        organisation,
+        created_at: user.created_at,
+        roles: user.roles.clone(),
+    }
+}
+
+/// hydrate_badge resolves the owner and the issuer of the stored badge.
+/// Users and organisations are never removed, so both are expected to exist.
+pub fn hydrate_badge(badge: &StoredBadge) -> Badge {
+    let owner = authenticated_user(badge.owner_principal).unwrap_or_else(|| {
+        panic!(
+            "Owner {} of badge with id {} not found.",
+            badge.owner_principal, badge.id
+        )
     });
+    let issuer = ORGANISATIONS
+        .with(|orgs| orgs.borrow().get(&badge.issuer_id))
+        .unwrap_or_else(|| {
+            panic!(
+                "Issuer {} of badge with id {} not found.",
+                badge.issuer_id, badge.id
+            )
+        });
 
-    if !is_approved {
-        result.claims = Vec::new();
+    Badge {
+        id: badge.id,
+        title: badge.title.clone(),
+        description: badge.description.clone(),
+        badge_type: badge.badge_type,
+        issuer,
+        owner,
+        status: badge.status,
+        revocation: badge.revocation.clone(),
+        claims: badge.claims.clone(),
+        withheld_claim_keys: Vec::new(),
+        signed_by: badge.signed_by.clone(),
+        created_at: badge.created_at,
+        expires_at: badge.expires_at,
+        provenance: badge.provenance.clone(),
+    }
+}
+
+/// find_access_request returns the access request with the given ID
+/// together with the principal of the badge owner it was sent to.
+pub fn find_access_request(request_id: u128) -> Option<(Principal, StoredAccessRequest)> {
+    let owner = index::request_owner(request_id)?;
+    ACCESS_REQUESTS.with(|requests| {
+        requests
+            .borrow()
+            .get(&owner.into())
+            .and_then(|stored| stored.0.into_iter().find(|r| r.id == request_id))
+            .map(|r| (owner, r))
+    })
+}
+
+/// save_access_request replaces the stored access request sent to the given badge owner.
+pub fn save_access_request(owner: Principal, request: StoredAccessRequest) {
+    index::update_access_request(owner, &request);
+    ACCESS_REQUESTS.with(|requests| {
+        let mut requests = requests.borrow_mut();
+        let mut stored = requests.get(&owner.into()).unwrap_or_default().0;
+        match stored.iter().position(|r| r.id == request.id) {
+            Some(index) => stored[index] = request,
+            None => stored.push(request),
+        }
+        requests.insert(owner.into(), StorableVec(stored));
+    });
+}
+
+/// can_review_role_request returns true if the user can approve or reject the role request.
+/// Role requests are reviewed by the administrators of the requested organisation,
+/// or by any administrator if the organisation has none yet.
+pub fn can_review_role_request(user: &User, request: &RoleRequest) -> bool {
+    if !user.is_admin() {
+        return false;
+    }
+    if user.organisation.id == request.organisation_id {
+        return true;
+    }
+    PRINCIPALS.with(|principals| {
+        !principals
+            .borrow()
+            .values()
+            .any(|it| it.is_admin() && it.organisation_id == request.organisation_id)
+    })
+}
+
+/// save_badge stores the badge and updates the search index.
+pub fn save_badge(badge: StoredBadge) {
+    let previous = BADGES.with(|badges| badges.borrow_mut().insert(badge.id, badge.clone()));
+    index::update_badge(previous.as_ref(), &badge);
+    certification::certify_badge(&badge);
+}
+
+/// verify_badge returns the public verification data of the badge
+/// together with the certificate and witness of its certified state.
+pub fn verify_badge(badge_id: u128) -> Result<CertifiedBadgeVerification, Error> {
+    if index::is_rebuilding() {
+        return Err(Error::unavailable(
+            "Badges are being certified, please try again shortly.",
+        ));
+    }
+
+    let badge = BADGES.with(|badges| badges.borrow().get(&badge_id));
+    if badge.is_none() {
+        return Err(Error::not_found("badge", badge_id));
+    }
+    let badge = badge.unwrap();
+
+    let issuer = ORGANISATIONS.with(|orgs| orgs.borrow().get(&badge.issuer_id));
+    if issuer.is_none() {
+        return Err(Error::not_found("organisation", badge.issuer_id));
+    }
+
+    Ok(CertifiedBadgeVerification {
+        verification: BadgeVerification::new(&with_expiry(&badge), issuer.unwrap()),
+        state: CertifiedBadgeState::from(&badge),
+        certificate: certification::certificate(),
+        witness: certification::witness(badge_id),
+    })
+}
+
+/// to_hex encodes the bytes as a lowercase hex string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// format_date formats a timestamp in nanoseconds since the epoch as a UTC date.
+pub fn format_date(nanos: u64) -> String {
+    let days = (nanos / 1_000_000_000 / 86_400) as i64;
+
+    // Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// format_timestamp formats a timestamp in nanoseconds since the epoch as an RFC 3339 UTC date-time.
+pub fn format_timestamp(nanos: u64) -> String {
+    let seconds = nanos / 1_000_000_000 % 86_400;
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(nanos),
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// parse_timestamp parses an RFC 3339 date-time, like `2024-05-01T12:00:00.000+02:00`,
+/// into nanoseconds since the epoch. Fractions of seconds are ignored.
+/// Returns `None` for invalid dates and times, and for dates that do not fit into a `u64` timestamp.
+pub fn parse_timestamp(value: &str) -> Option<u64> {
+    fn number(text: &str, range: std::ops::Range<usize>) -> Option<i64> {
+        let digits = text.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    let (year, month, day) = (
+        number(value, 0..4)?,
+        number(value, 5..7)?,
+        number(value, 8..10)?,
+    );
+    let (hour, minute, second) = (
+        number(value, 11..13)?,
+        number(value, 14..16)?,
+        number(value, 17..19)?,
+    );
+    if !matches!(value.get(10..11)?, "T" | "t" | " ")
+        || value.get(4..5)? != "-"
+        || value.get(7..8)? != "-"
+        || value.get(13..14)? != ":"
+        || value.get(16..17)? != ":"
+        || !(1..=12).contains(&month)
+    {
+        return None;
+    }
+
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    // Leap seconds are allowed by RFC 3339.
+    if !(1..=days_in_month).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Fractions of seconds are a dot followed by at least one digit.
+    let zone = match value[19..].strip_prefix('.') {
+        Some(fraction) => {
+            let zone = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+            if zone.len() == fraction.len() {
+                return None;
+            }
+            zone
+        }
+        None => &value[19..],
+    };
+    let offset = match zone {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match zone.get(0..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = (number(zone, 1..3)?, number(zone, 4..6)?);
+            if zone.len() != 6 || zone.get(3..4)? != ":" || hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 3_600 + minutes * 60)
+        }
+    };
+
+    // Converts a civil date to days since the epoch, the inverse of `format_date`.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second - offset;
+    u64::try_from(seconds).ok()?.checked_mul(1_000_000_000)
+}
+
+/// with_expiry returns a copy of the badge marked as expired if its expiry date has passed,
+/// even when the periodic expiry job has not processed it yet.
+pub fn with_expiry(badge: &StoredBadge) -> StoredBadge {
+    let mut result = badge.clone();
+    if badge.has_expired(time()) {
+        result.status = BadgeStatus::Expired;
     }
     result
 }
 
+/// expire_badges marks all badges whose expiry date has passed as expired
+/// and removes the access approvals granted for them.
+/// Only the badges due according to the expiry index are loaded.
+pub fn expire_badges() {
+    let now = time();
+
+    let expired: Vec<StoredBadge> = BADGES.with(|badges| {
+        let badges = badges.borrow();
+        index::badges_due_to_expire(now)
+            .into_iter()
+            .filter_map(|id| badges.get(&id))
+            .filter(|badge| badge.status != BadgeStatus::Expired && badge.has_expired(now))
+            .collect()
+    });
+
+    for mut badge in expired {
+        badge.status = BadgeStatus::Expired;
+        BADGE_ACCESS_APPROVALS.with(|approvals| approvals.borrow_mut().remove(&badge.id));
+        save_badge(badge);
+    }
+}
+
 pub fn generate_organisations() {
     let initial: Vec<String> = vec![
         String::from("Zurich University of Applied Sciences"),
@@ -57,16 +407,19 @@ pub fn generate_organisations() {
 
     ORGANISATIONS.with(|orgs| {
         let mut orgs = orgs.borrow_mut();
-        let mut id: u128 = 1;
         let created_at = time();
         for name in initial {
             let org = Organisation {
-                id,
+                id: next_id(IdKind::Organisation),
                 name,
+                description: None,
+                website: None,
+                country: Some(String::from("Switzerland")),
+                logo: None,
                 created_at,
+                archived_at: None,
             };
             orgs.insert(org.id, org);
-            id += 1;
         }
     });
 }
@@ -87,3 +440,94 @@ pub fn generate_roles() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, format_timestamp, parse_timestamp};
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn formats_dates_and_timestamps() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_date(951_782_400 * SECOND), "2000-02-29");
+        assert_eq!(
+            format_timestamp(1_714_564_800 * SECOND + 999_999_999),
+            "2024-05-01T12:00:00Z"
+        );
+        assert_eq!(format_timestamp(u64::MAX), "2554-07-21T23:34:33Z");
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        for nanos in [
+            0,
+            951_782_400 * SECOND,
+            1_714_564_800 * SECOND,
+            18_446_744_073 * SECOND,
+        ] {
+            assert_eq!(parse_timestamp(&format_timestamp(nanos)), Some(nanos));
+        }
+    }
+
+    #[test]
+    fn parses_fractions_and_offsets() {
+        let noon = Some(1_714_564_800 * SECOND);
+        assert_eq!(parse_timestamp("2024-05-01T12:00:00Z"), noon);
+        assert_eq!(parse_timestamp("2024-05-01t12:00:00z"), noon);
+        assert_eq!(parse_timestamp("2024-05-01 12:00:00Z"), noon);
+        assert_eq!(parse_timestamp("2024-05-01T12:00:00.123Z"), noon);
+        assert_eq!(parse_timestamp("2024-05-01T14:00:00.000+02:00"), noon);
+        assert_eq!(parse_timestamp("2024-05-01T07:30:00-04:30"), noon);
+        assert_eq!(
+            parse_timestamp("2016-12-31T23:59:60Z"),
+            Some(1_483_228_800 * SECOND)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        for value in [
+            "",
+            "2024-05-01",
+            "2024-05-01T12:00:00",
+            "2024-05-01T12:00:00123Z",
+            "2024-05-01T12:00:00.Z",
+            "2024-05-01T12:00:00.12",
+            "2024/05/01T12:00:00Z",
+            "2024-05-01X12:00:00Z",
+            "2024-13-01T12:00:00Z",
+            "2024-00-01T12:00:00Z",
+            "2023-02-29T12:00:00Z",
+            "2024-04-31T12:00:00Z",
+            "2024-05-01T24:00:00Z",
+            "2024-05-01T12:60:00Z",
+            "2024-05-01T12:00:61Z",
+            "2024-05-01T12:00:00+2:00",
+            "2024-05-01T12:00:00+24:00",
+            "2024-05-01T12:00:00+02:00:00",
+            "+024-05-01T12:00:00Z",
+            "2024-05-01T12:00:00\u{e9}",
+        ] {
+            assert_eq!(parse_timestamp(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_timestamps_before_1970() {
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_timestamp("1970-01-01T01:00:00+02:00"), None);
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+    }
+
+    #[test]
+    fn rejects_timestamps_that_overflow() {
+        assert_eq!(
+            parse_timestamp("2554-07-21T23:34:33Z"),
+            Some(18_446_744_073 * SECOND)
+        );
+        assert_eq!(parse_timestamp("2554-07-21T23:34:34Z"), None);
+        assert_eq!(parse_timestamp("9999-12-31T23:59:59Z"), None);
+    }
+}
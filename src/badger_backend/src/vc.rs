@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::http;
+use crate::model::Badge;
+use crate::util::format_timestamp;
+
+const CREDENTIALS_V2_CONTEXT: &str = "https://www.w3.org/ns/credentials/v2";
+
+/// to_verifiable_credential renders the badge as a W3C Verifiable Credential following the
+/// VC Data Model 2.0. The proof of the canister signature is attached if the badge has one,
+/// and the current status can be checked through the certified data `credentialStatus` points to.
+/// Only the claims left on the badge are included, so it has to be masked for the caller first.
+pub fn to_verifiable_credential(badge: &Badge, proof: Option<Value>) -> Value {
+    let url = http::badge_url(badge.id);
+
+    let claims: BTreeMap<&str, &str> = badge
+        .claims
+        .iter()
+        .map(|claim| (claim.key.as_str(), claim.value.as_str()))
+        .collect();
+
+    let mut issuer = json!({
+        "id": format!("urn:badger:organisation:{}", badge.issuer.id),
+        "name": badge.issuer.name,
+    });
+    if let Some(website) = &badge.issuer.website {
+        issuer["url"] = json!(website);
+    }
+
+    let mut credential = json!({
+        "@context": [CREDENTIALS_V2_CONTEXT],
+        "id": url,
+        "type": ["VerifiableCredential", "BadgeCredential"],
+        "name": badge.title,
+        "issuer": issuer,
+        "validFrom": format_timestamp(badge.created_at),
+        "credentialSubject": {
+            "id": format!("did:icp:{}", badge.owner.principal_id),
+            "name": badge.owner.name,
+            "badgeType": badge.badge_type,
+            "claims": claims,
+        },
+        "credentialStatus": {
+            "id": format!("{}.json", url),
+            "type": "BadgerBadgeStatus",
+            "status": badge.status,
+        },
+    });
+
+    if let Some(description) = &badge.description {
+        credential["description"] = json!(description);
+    }
+    if let Some(expires_at) = badge.expires_at {
+        credential["validUntil"] = json!(format_timestamp(expires_at));
+    }
+    if let Some(revocation) = &badge.revocation {
+        credential["credentialStatus"]["revokedAt"] =
+            json!(format_timestamp(revocation.revoked_at));
+    }
+
+    if let Some(proof) = proof {
+        credential["proof"] = proof;
+    }
+
+    credential
+}
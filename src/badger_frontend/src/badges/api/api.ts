@@ -1,37 +1,89 @@
 import {
+  AccessGrant,
   AccessRequest,
   Badge,
+  BadgeSearch,
+  BadgeSort,
+  CertifiedBadgeVerification,
+  NewAccessRequest,
   NewBadgeRequest,
+  NewImportedBadge,
+  NewOrganisationRequest,
+  NewRevocation,
   NewUserRequest,
   OptionalBigInt,
   OptionalText,
   Organisation,
+  Page,
+  PageRequest,
   Result,
   Role,
+  RoleRequest,
+  SentAccessRequest,
+  SigningPublicKey,
   User,
+  UserSort,
+  UserUpdate,
 } from "../models";
 
 export interface BadgesAPI {
-  getAll(principalID: OptionalText, organisationID: OptionalBigInt): Promise<Result<Badge[]>>;
+  getAll(
+    principalID: OptionalText,
+    organisationID: OptionalBigInt,
+    sort: [] | [BadgeSort],
+    page: [] | [PageRequest]
+  ): Promise<Result<Page<Badge>>>;
+  search(search: BadgeSearch, sort: [] | [BadgeSort], page: [] | [PageRequest]): Promise<Result<Page<Badge>>>;
   getOne(badgeID: bigint): Promise<Result<Badge>>;
+  verify(badgeID: bigint): Promise<Result<CertifiedBadgeVerification>>;
+  exportVC(badgeID: bigint): Promise<Result<string>>;
+  exportOB(badgeID: bigint): Promise<Result<string>>;
+  importOB(badge: NewImportedBadge): Promise<Result<Badge>>;
+  signingPublicKey(): Promise<Result<SigningPublicKey>>;
   createOne(badge: NewBadgeRequest): Promise<Result<Badge>>;
-  revokeOne(badgeID: bigint): Promise<Result<boolean>>;
+  revokeOne(badgeID: bigint, revocation: NewRevocation): Promise<Result<boolean>>;
+  suspendOne(badgeID: bigint): Promise<Result<boolean>>;
+  reinstateOne(badgeID: bigint): Promise<Result<boolean>>;
 }
 
 export interface OrganisationsAPI {
   getAll(): Promise<Result<Organisation[]>>;
+  getOne(organisationID: bigint): Promise<Result<Organisation>>;
+  createOne(organisation: NewOrganisationRequest): Promise<Result<Organisation>>;
+  updateOne(organisationID: bigint, organisation: NewOrganisationRequest): Promise<Result<Organisation>>;
+  archiveOne(organisationID: bigint): Promise<Result<boolean>>;
 }
 
 export interface UsersAPI {
-  getAll(organisation_id: OptionalBigInt, role_id: OptionalBigInt): Promise<Result<User[]>>;
+  getAll(
+    organisation_id: OptionalBigInt,
+    role_id: OptionalBigInt,
+    sort: [] | [UserSort],
+    page: [] | [PageRequest]
+  ): Promise<Result<Page<User>>>;
   getOne(principalID: string): Promise<Result<User>>;
   getWhoAmI(): Promise<Result<User>>;
   createOne(user: NewUserRequest): Promise<Result<User>>;
+  updateMe(update: UserUpdate): Promise<Result<User>>;
+  setRoles(principalID: string, roleIDs: bigint[]): Promise<Result<User>>;
+  moveOrganisation(principalID: string, organisationID: bigint): Promise<Result<User>>;
   getAllRoles(): Promise<Result<Role[]>>;
+  getAllRoleRequests(): Promise<Result<RoleRequest[]>>;
+  approveRoleRequest(principalID: string): Promise<Result<RoleRequest>>;
+  rejectRoleRequest(principalID: string): Promise<Result<RoleRequest>>;
 }
 
 export interface AccessRequestsAPI {
   getAll(): Promise<Result<AccessRequest[]>>;
-  createOne(badgeID: bigint): Promise<Result<AccessRequest>>;
-  approveOne(accessRequestID: bigint): Promise<Result<boolean>>;
+  getSent(): Promise<Result<SentAccessRequest[]>>;
+  createOne(request: NewAccessRequest): Promise<Result<AccessRequest>>;
+  approveOne(
+    accessRequestID: bigint,
+    expiresAt: OptionalBigInt,
+    claimKeys: [] | [string[]]
+  ): Promise<Result<boolean>>;
+  denyOne(accessRequestID: bigint): Promise<Result<boolean>>;
+  withdrawOne(accessRequestID: bigint): Promise<Result<boolean>>;
+  getAllGrants(badgeID: bigint): Promise<Result<AccessGrant[]>>;
+  revokeGrant(badgeID: bigint, principalID: string): Promise<Result<boolean>>;
 }
@@ -1,15 +1,41 @@
 import { BackendActor } from "../../../lib/backend";
-import { AccessRequest, Result } from "../../models";
+import {
+  AccessGrant,
+  AccessRequest,
+  NewAccessRequest,
+  OptionalBigInt,
+  Result,
+  SentAccessRequest,
+} from "../../models";
 import { AccessRequestsAPI } from "../api";
 
 export const accessRequestsAPI = (actor: BackendActor): AccessRequestsAPI => ({
   async getAll(): Promise<Result<Array<AccessRequest>>> {
     return (await actor.requests_get_all()) as Result<Array<AccessRequest>>;
   },
-  async createOne(badgeID): Promise<Result<AccessRequest>> {
-    return (await actor.requests_create_one(badgeID)) as Result<AccessRequest>;
+  async getSent(): Promise<Result<Array<SentAccessRequest>>> {
+    return (await actor.requests_get_sent()) as Result<Array<SentAccessRequest>>;
   },
-  async approveOne(accessRequestID: bigint): Promise<Result<boolean>> {
-    return (await actor.requests_approve_one(accessRequestID)) as Result<boolean>;
+  async createOne(request: NewAccessRequest): Promise<Result<AccessRequest>> {
+    return (await actor.requests_create_one(request)) as Result<AccessRequest>;
+  },
+  async approveOne(
+    accessRequestID: bigint,
+    expiresAt: OptionalBigInt,
+    claimKeys: [] | [string[]]
+  ): Promise<Result<boolean>> {
+    return (await actor.requests_approve_one(accessRequestID, expiresAt, claimKeys)) as Result<boolean>;
+  },
+  async denyOne(accessRequestID: bigint): Promise<Result<boolean>> {
+    return (await actor.requests_deny_one(accessRequestID)) as Result<boolean>;
+  },
+  async withdrawOne(accessRequestID: bigint): Promise<Result<boolean>> {
+    return (await actor.requests_withdraw_one(accessRequestID)) as Result<boolean>;
+  },
+  async getAllGrants(badgeID: bigint): Promise<Result<Array<AccessGrant>>> {
+    return (await actor.approvals_get_all(badgeID)) as Result<Array<AccessGrant>>;
+  },
+  async revokeGrant(badgeID: bigint, principalID: string): Promise<Result<boolean>> {
+    return (await actor.approvals_revoke_one(badgeID, principalID)) as Result<boolean>;
   },
 });
@@ -1,18 +1,65 @@
 import { BackendActor } from "../../../lib/backend";
-import { Badge, NewBadgeRequest, OptionalBigInt, OptionalText, Result } from "../../models";
+import {
+  Badge,
+  BadgeSearch,
+  BadgeSort,
+  CertifiedBadgeVerification,
+  NewBadgeRequest,
+  NewImportedBadge,
+  NewRevocation,
+  OptionalBigInt,
+  OptionalText,
+  Page,
+  PageRequest,
+  Result,
+  SigningPublicKey,
+} from "../../models";
 import { BadgesAPI } from "../api";
 
 export const badgesAPI = (actor: BackendActor): BadgesAPI => ({
-  async getAll(principalID: OptionalText, organisationID: OptionalBigInt): Promise<Result<Array<Badge>>> {
-    return (await actor.badges_get_all(principalID, organisationID)) as Result<Array<Badge>>;
+  async getAll(
+    principalID: OptionalText,
+    organisationID: OptionalBigInt,
+    sort: [] | [BadgeSort],
+    page: [] | [PageRequest]
+  ): Promise<Result<Page<Badge>>> {
+    return (await actor.badges_get_all(principalID, organisationID, sort, page)) as Result<Page<Badge>>;
+  },
+  async search(
+    search: BadgeSearch,
+    sort: [] | [BadgeSort],
+    page: [] | [PageRequest]
+  ): Promise<Result<Page<Badge>>> {
+    return (await actor.badges_search(search, sort, page)) as Result<Page<Badge>>;
   },
   async getOne(badgeID: bigint): Promise<Result<Badge>> {
     return (await actor.badges_get_one(badgeID)) as Result<Badge>;
   },
+  async verify(badgeID: bigint): Promise<Result<CertifiedBadgeVerification>> {
+    return (await actor.badges_verify(badgeID)) as Result<CertifiedBadgeVerification>;
+  },
+  async exportVC(badgeID: bigint): Promise<Result<string>> {
+    return (await actor.badges_export_vc(badgeID)) as Result<string>;
+  },
+  async exportOB(badgeID: bigint): Promise<Result<string>> {
+    return (await actor.badges_export_ob(badgeID)) as Result<string>;
+  },
+  async importOB(badge: NewImportedBadge): Promise<Result<Badge>> {
+    return (await actor.badges_import_ob(badge)) as Result<Badge>;
+  },
+  async signingPublicKey(): Promise<Result<SigningPublicKey>> {
+    return (await actor.signing_public_key()) as Result<SigningPublicKey>;
+  },
   async createOne(badge: NewBadgeRequest): Promise<Result<Badge>> {
     return (await actor.badges_create_one(badge)) as Result<Badge>;
   },
-  async revokeOne(badgeID: bigint): Promise<Result<boolean>> {
-    return (await actor.badges_revoke_one(badgeID)) as Result<boolean>;
+  async revokeOne(badgeID: bigint, revocation: NewRevocation): Promise<Result<boolean>> {
+    return (await actor.badges_revoke_one(badgeID, revocation)) as Result<boolean>;
+  },
+  async suspendOne(badgeID: bigint): Promise<Result<boolean>> {
+    return (await actor.badges_suspend_one(badgeID)) as Result<boolean>;
+  },
+  async reinstateOne(badgeID: bigint): Promise<Result<boolean>> {
+    return (await actor.badges_reinstate_one(badgeID)) as Result<boolean>;
   },
 });
@@ -1,9 +1,21 @@
 import { BackendActor } from "../../../lib/backend";
-import { Organisation, Result } from "../../models";
+import { NewOrganisationRequest, Organisation, Result } from "../../models";
 import { OrganisationsAPI } from "../api";
 
 export const organisationsAPI = (actor: BackendActor): OrganisationsAPI => ({
   async getAll(): Promise<Result<Array<Organisation>>> {
-    return (await actor.organisations_get_all()) as Result<Array<Organisation>>;
+    return (await actor.organisations_get_all([])) as Result<Array<Organisation>>;
+  },
+  async getOne(organisationID: bigint): Promise<Result<Organisation>> {
+    return (await actor.organisations_get_one(organisationID)) as Result<Organisation>;
+  },
+  async createOne(organisation: NewOrganisationRequest): Promise<Result<Organisation>> {
+    return (await actor.organisations_create_one(organisation)) as Result<Organisation>;
+  },
+  async updateOne(organisationID: bigint, organisation: NewOrganisationRequest): Promise<Result<Organisation>> {
+    return (await actor.organisations_update_one(organisationID, organisation)) as Result<Organisation>;
+  },
+  async archiveOne(organisationID: bigint): Promise<Result<boolean>> {
+    return (await actor.organisations_archive_one(organisationID)) as Result<boolean>;
   },
 });
@@ -1,10 +1,26 @@
 import { BackendActor } from "../../../lib/backend";
-import { NewUserRequest, OptionalBigInt, Result, Role, User } from "../../models";
+import {
+  NewUserRequest,
+  OptionalBigInt,
+  Page,
+  PageRequest,
+  Result,
+  Role,
+  RoleRequest,
+  User,
+  UserSort,
+  UserUpdate,
+} from "../../models";
 import { UsersAPI } from "../api";
 
 export const usersAPI = (actor: BackendActor): UsersAPI => ({
-  async getAll(organisation_id: OptionalBigInt, role_id: OptionalBigInt): Promise<Result<Array<User>>> {
-    return (await actor.users_get_all(organisation_id, role_id)) as Result<Array<User>>;
+  async getAll(
+    organisation_id: OptionalBigInt,
+    role_id: OptionalBigInt,
+    sort: [] | [UserSort],
+    page: [] | [PageRequest]
+  ): Promise<Result<Page<User>>> {
+    return (await actor.users_get_all(organisation_id, role_id, sort, page)) as Result<Page<User>>;
   },
   async getOne(principalID: string): Promise<Result<User>> {
     return (await actor.users_get_one(principalID)) as Result<User>;
@@ -15,7 +31,25 @@ export const usersAPI = (actor: BackendActor): UsersAPI => ({
   async createOne(user: NewUserRequest): Promise<Result<User>> {
     return (await actor.users_create_one(user)) as Result<User>;
   },
+  async updateMe(update: UserUpdate): Promise<Result<User>> {
+    return (await actor.users_update_me(update)) as Result<User>;
+  },
+  async setRoles(principalID: string, roleIDs: bigint[]): Promise<Result<User>> {
+    return (await actor.users_set_roles(principalID, roleIDs)) as Result<User>;
+  },
+  async moveOrganisation(principalID: string, organisationID: bigint): Promise<Result<User>> {
+    return (await actor.users_move_organisation(principalID, organisationID)) as Result<User>;
+  },
   async getAllRoles(): Promise<Result<Array<Role>>> {
     return (await actor.roles_get_all()) as Result<Array<Role>>;
   },
+  async getAllRoleRequests(): Promise<Result<Array<RoleRequest>>> {
+    return (await actor.role_requests_get_all()) as Result<Array<RoleRequest>>;
+  },
+  async approveRoleRequest(principalID: string): Promise<Result<RoleRequest>> {
+    return (await actor.role_requests_approve_one(principalID)) as Result<RoleRequest>;
+  },
+  async rejectRoleRequest(principalID: string): Promise<Result<RoleRequest>> {
+    return (await actor.role_requests_reject_one(principalID)) as Result<RoleRequest>;
+  },
 });
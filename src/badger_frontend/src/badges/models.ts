@@ -7,17 +7,108 @@ export const LECTURER_ROLE_ID = 2n;
 export const ADMINISTRATION_ROLE_ID = 3n;
 export const COMPANY_ROLE_ID = 4n;
 
-export type Result<T> = { ok: T } | { error: string };
+export type ApiError =
+  | { notFound: { entity: string; id: string } }
+  | { unauthorized: { principal: string } }
+  | { forbidden: { principal: string; message: string } }
+  | { invalidInput: { field: string; message: string } }
+  | { conflict: { message: string } }
+  | { unavailable: { message: string } };
+
+export type Result<T> = { ok: T } | { error: ApiError };
+
+export interface PageRequest {
+  cursor: OptionalText;
+  limit: [] | [number];
+}
+
+export interface Page<T> {
+  items: T[];
+  nextCursor: OptionalText;
+  total: bigint;
+}
+
+export type SortOrder = { asc: null } | { desc: null };
+
+export interface BadgeSort {
+  by: { createdAt: null } | { title: null } | { ownerName: null };
+  order: SortOrder;
+}
+
+export interface UserSort {
+  by: { createdAt: null } | { name: null };
+  order: SortOrder;
+}
 
 export function isOK<T>(result: Result<T>): result is { ok: T } {
   return "ok" in result;
 }
 
+// Follows the cursors of a paginated endpoint until all items have been loaded
+export async function fetchAllPages<T>(
+  fetchPage: (page: [] | [PageRequest]) => Promise<Result<Page<T>>>,
+): Promise<Result<T[]>> {
+  const items: T[] = [];
+  let cursor: OptionalText = [];
+  for (;;) {
+    const result = await fetchPage([{ cursor, limit: [] }]);
+    if (!isOK(result)) return result;
+    items.push(...result.ok.items);
+    if (result.ok.nextCursor.length === 0) return { ok: items };
+    cursor = result.ok.nextCursor;
+  }
+}
+
+export function errorMessage(error: ApiError): string {
+  if ("notFound" in error) return `${error.notFound.entity} ${error.notFound.id} not found.`;
+  if ("unauthorized" in error) return `User with principal ${error.unauthorized.principal} not found.`;
+  if ("forbidden" in error) return error.forbidden.message;
+  if ("invalidInput" in error) return error.invalidInput.message;
+  if ("unavailable" in error) return error.unavailable.message;
+  return error.conflict.message;
+}
+
 export interface Claim {
   key: string;
   value: string;
 }
 
+export type BadgeStatus = { active: null } | { suspended: null } | { revoked: null } | { expired: null };
+
+export interface ClaimFilter {
+  key: string;
+  value: OptionalText;
+}
+
+export interface BadgeSearch {
+  text: OptionalText;
+  claim: [] | [ClaimFilter];
+  badgeType: [] | [number];
+  status: [] | [BadgeStatus];
+  issuerID: OptionalBigInt;
+  createdAfter: OptionalBigInt;
+  createdBefore: OptionalBigInt;
+}
+
+export type RevocationReason =
+  | { issuedInError: null }
+  | { academicMisconduct: null }
+  | { superseded: null }
+  | { requestedByOwner: null }
+  | { other: null };
+
+export interface NewRevocation {
+  reason: RevocationReason;
+  details: OptionalText;
+}
+
+export interface Revocation {
+  reason: RevocationReason;
+  details: OptionalText;
+  revokedBy: string;
+  revokedAt: bigint;
+}
+
 export interface Badge {
   badgeID: bigint;
   title: string;
@@ -25,11 +116,38 @@ export interface Badge {
   badgeType: number;
   issuer: Organisation;
   owner: User;
-  isRevoked: boolean;
+  status: BadgeStatus;
+  revocation: [] | [Revocation];
   // isFullAccess: boolean;
   claims: Claim[];
+  withheldClaimKeys: string[];
   signedBy: string[];
   createdAt: string;
+  expiresAt: OptionalBigInt;
+  provenance: [] | [Provenance];
+}
+
+export interface SigningPublicKey {
+  algorithm: string;
+  keyName: string;
+  publicKey: Uint8Array | number[];
+}
+
+export interface Alignment {
+  targetName: string;
+  targetUrl: string;
+  targetFramework: OptionalText;
+}
+
+export interface Provenance {
+  source: { openBadges: null };
+  credentialID: OptionalText;
+  issuerID: OptionalText;
+  issuerName: string;
+  criteria: OptionalText;
+  alignments: Alignment[];
+  importedBy: string;
+  importedAt: bigint;
 }
 
 export interface NewBadgeRequest {
@@ -39,12 +157,32 @@ export interface NewBadgeRequest {
   issuerID: bigint;
   ownerID: string;
   claims: Claim[];
+  expiresAt: OptionalBigInt;
+}
+
+export interface NewImportedBadge {
+  ownerID: string;
+  badgeType: number;
+  credential: string;
 }
 
 export interface Organisation {
   organisationID: bigint;
   name: string;
+  description: OptionalText;
+  website: OptionalText;
+  country: OptionalText;
+  logo: [] | [FileLocation];
   createdAt: string;
+  archivedAt: OptionalBigInt;
+}
+
+export interface NewOrganisationRequest {
+  name: string;
+  description: OptionalText;
+  website: OptionalText;
+  country: OptionalText;
+  logo: [] | [FileLocation];
 }
 
 export interface User {
@@ -63,18 +201,116 @@ export interface NewUserRequest {
   roles: bigint[];
 }
 
+export interface UserUpdate {
+  name: string;
+  email: string;
+}
+
 export interface FileLocation {
   location: string;
 }
 
+export type LegalBasis =
+  | { consent: null }
+  | { contract: null }
+  | { legalObligation: null }
+  | { vitalInterests: null }
+  | { publicTask: null }
+  | { legitimateInterests: null };
+
+export interface NewAccessRequest {
+  badgeID: bigint;
+  claimKeys: string[];
+  purpose: string;
+  message: OptionalText;
+  legalBasis: LegalBasis;
+}
+
+export type AccessRequestStatus = { pending: null } | { approved: null } | { denied: null } | { withdrawn: null };
+
 export interface AccessRequest {
   accessRequestID: bigint;
   user: User;
   badge: Badge;
+  claimKeys: [] | [string[]];
+  purpose: OptionalText;
+  message: OptionalText;
+  legalBasis: [] | [LegalBasis];
+  status: AccessRequestStatus;
   createdAt: string;
+  decidedAt: OptionalBigInt;
+}
+
+export interface BadgeSummary {
+  badgeID: bigint;
+  title: string;
+  badgeType: number;
+  issuer: Organisation;
+  status: BadgeStatus;
+  expiresAt: OptionalBigInt;
+}
+
+export interface BadgeVerification {
+  badgeID: bigint;
+  valid: boolean;
+  title: string;
+  issuer: Organisation;
+  status: BadgeStatus;
+  issuedAt: bigint;
+  expiresAt: OptionalBigInt;
+  revokedAt: OptionalBigInt;
+  revocationReason: [] | [RevocationReason];
+  provenance: [] | [Provenance];
+}
+
+export interface CertifiedBadgeState {
+  badgeID: bigint;
+  issuerID: bigint;
+  status: BadgeStatus;
+  issuedAt: bigint;
+  expiresAt: OptionalBigInt;
+  revokedAt: OptionalBigInt;
+  imported: boolean;
+}
+
+export interface CertifiedBadgeVerification {
+  verification: BadgeVerification;
+  state: CertifiedBadgeState;
+  certificate: [] | [Uint8Array | number[]];
+  witness: Uint8Array | number[];
+}
+
+export interface SentAccessRequest {
+  accessRequestID: bigint;
+  badge: BadgeSummary;
+  claimKeys: [] | [string[]];
+  purpose: OptionalText;
+  legalBasis: [] | [LegalBasis];
+  status: AccessRequestStatus;
+  createdAt: bigint;
+  decidedAt: OptionalBigInt;
+}
+
+export interface AccessGrant {
+  principalID: string;
+  grantedAt: bigint;
+  expiresAt: OptionalBigInt;
+  claimKeys: [] | [string[]];
 }
 
 export interface Role {
   roleID: bigint;
   name: string;
 }
+
+export type RoleRequestStatus = { pending: null } | { approved: null } | { rejected: null };
+
+export interface RoleRequest {
+  principalID: string;
+  organisationID: bigint;
+  roles: Role[];
+  status: RoleRequestStatus;
+  createdAt: bigint;
+  decidedBy: OptionalText;
+  decidedAt: OptionalBigInt;
+}
@@ -0,0 +1,98 @@
+import { Form, Formik, FormikConfig, FormikHelpers } from "formik";
+import React from "react";
+import * as Yup from "yup";
+import { LegalBasis } from "../../badges/models";
+import { SelectField, TextAreaField, TextField } from "../fields";
+
+export interface AccessRequestFormValues {
+  legalBasis: string;
+  purpose: string;
+  message?: string;
+}
+
+interface AccessRequestFormProps {
+  title: string;
+  onSubmit: (values: AccessRequestFormValues, helpers: FormikHelpers<AccessRequestFormValues>) => void;
+  onCancel: () => void;
+  disabled?: boolean;
+}
+
+/**
+ * The legal bases of the GDPR that a company can state for processing the claims of a badge.
+ */
+const LEGAL_BASES: { value: string; label: string }[] = [
+  { value: "consent", label: "Consent of the badge owner" },
+  { value: "contract", label: "Performance of a contract" },
+  { value: "legalObligation", label: "Legal obligation" },
+  { value: "vitalInterests", label: "Vital interests" },
+  { value: "publicTask", label: "Public task" },
+  { value: "legitimateInterests", label: "Legitimate interests" },
+];
+
+/**
+ * Converts the value of the legal basis selector into the variant expected by the backend.
+ */
+export function toLegalBasis(value: string): LegalBasis {
+  return { [value]: null } as LegalBasis;
+}
+
+export const AccessRequestForm: React.FC<AccessRequestFormProps> = (props) => {
+  const config: FormikConfig<AccessRequestFormValues> = {
+    initialValues: {
+      legalBasis: "",
+      purpose: "",
+      message: "",
+    },
+    onSubmit: (values: AccessRequestFormValues, helpers: FormikHelpers<AccessRequestFormValues>) => {
+      props.onSubmit(values, helpers);
+    },
+    validationSchema: Yup.object({
+      legalBasis: Yup.string()
+        .oneOf(LEGAL_BASES.map((basis) => basis.value), "Legal basis is required")
+        .required("Legal basis is required"),
+      purpose: Yup.string().trim().required("Purpose is required"),
+      message: Yup.string(),
+    }),
+  };
+
+  return (
+    <Formik {...config}>
+      <Form className="bg-white shadow-md rounded px-8 pt-6 pb-8 mb-4">
+        <div className="flex flex-wrap -mx-3 mb-4">
+          <div className="w-full px-3">
+            <h2 className="text-lg font-bold">Request access to {props.title}</h2>
+          </div>
+        </div>
+        <div className="flex flex-wrap -mx-3 mb-3">
+          <div className="w-full px-3 mb-6 md:mb-0">
+            <SelectField
+              name="legalBasis"
+              label="Legal basis"
+              options={[{ value: "", label: "Select the legal basis of the request" }, ...LEGAL_BASES]}
+              disabled={props.disabled}
+            />
+          </div>
+        </div>
+        <div className="flex flex-wrap -mx-3 mb-3">
+          <div className="w-full px-3 mb-6 md:mb-0">
+            <TextField name="purpose" label="Purpose" disabled={props.disabled} />
+          </div>
+        </div>
+        <TextAreaField name="message" label="Message to the owner" rows={3} disabled={props.disabled} />
+        <div className="flex items-end space-x-2">
+          <button className="px-4 py-2 mt-2 text-white bg-gray-800 rounded-md" type="submit" disabled={props.disabled}>
+            Request access
+          </button>
+          <button
+            className="px-4 py-2 mt-2 text-gray-800 bg-gray-200 rounded-md"
+            type="button"
+            disabled={props.disabled}
+            onClick={props.onCancel}
+          >
+            Cancel
+          </button>
+        </div>
+      </Form>
+    </Formik>
+  );
+};
@@ -0,0 +1,95 @@
+import { Form, Formik, FormikConfig, FormikHelpers } from "formik";
+import React from "react";
+import * as Yup from "yup";
+import { RevocationReason } from "../../badges/models";
+import { SelectField, TextAreaField } from "../fields";
+
+export interface RevocationFormValues {
+  reason: string;
+  details?: string;
+}
+
+interface RevocationFormProps {
+  onSubmit: (values: RevocationFormValues, helpers: FormikHelpers<RevocationFormValues>) => void;
+  onCancel: () => void;
+  disabled?: boolean;
+}
+
+/**
+ * The reasons a badge can be revoked for, see `RevocationReason`.
+ */
+const REVOCATION_REASONS: { value: string; label: string }[] = [
+  { value: "issuedInError", label: "Issued in error" },
+  { value: "academicMisconduct", label: "Academic misconduct" },
+  { value: "superseded", label: "Superseded by another badge" },
+  { value: "requestedByOwner", label: "Requested by the owner" },
+  { value: "other", label: "Other" },
+];
+
+/**
+ * Converts the value of the reason selector into the variant expected by the backend.
+ */
+export function toRevocationReason(value: string): RevocationReason {
+  return { [value]: null } as RevocationReason;
+}
+
+export const RevocationForm: React.FC<RevocationFormProps> = (props) => {
+  const config: FormikConfig<RevocationFormValues> = {
+    initialValues: {
+      reason: "",
+      details: "",
+    },
+    onSubmit: (values: RevocationFormValues, helpers: FormikHelpers<RevocationFormValues>) => {
+      props.onSubmit(values, helpers);
+    },
+    validationSchema: Yup.object({
+      reason: Yup.string()
+        .oneOf(REVOCATION_REASONS.map((reason) => reason.value), "Reason is required")
+        .required("Reason is required"),
+      details: Yup.string().when("reason", {
+        is: "other",
+        then: (schema) => schema.trim().required("Details are required for other reasons"),
+      }),
+    }),
+  };
+
+  return (
+    <Formik {...config}>
+      <Form className="bg-white shadow-md rounded px-8 pt-6 pb-8 mb-4">
+        <div className="flex flex-wrap -mx-3 mb-4">
+          <div className="w-full px-3">
+            <h2 className="text-lg font-bold">Revoke badge</h2>
+          </div>
+        </div>
+        <div className="flex flex-wrap -mx-3 mb-3">
+          <div className="w-full px-3 mb-6 md:mb-0">
+            <SelectField
+              name="reason"
+              label="Reason"
+              options={[{ value: "", label: "Select the reason of the revocation" }, ...REVOCATION_REASONS]}
+              disabled={props.disabled}
+            />
+          </div>
+        </div>
+        <TextAreaField name="details" label="Details" rows={3} disabled={props.disabled} />
+        <div className="flex items-end space-x-2">
+          <button
+            className="px-4 py-2 mt-2 text-white bg-red-600 rounded-md hover:bg-red-500"
+            type="submit"
+            disabled={props.disabled}
+          >
+            Revoke
+          </button>
+          <button
+            className="px-4 py-2 mt-2 text-gray-800 bg-gray-200 rounded-md"
+            type="button"
+            disabled={props.disabled}
+            onClick={props.onCancel}
+          >
+            Cancel
+          </button>
+        </div>
+      </Form>
+    </Formik>
+  );
+};
@@ -1,6 +1,6 @@
 import React, { useEffect } from "react";
 import { accessRequestsAPI } from "../../badges/api/remote/access_requests";
-import { AccessRequest, isOK } from "../../badges/models";
+import { AccessRequest, errorMessage, isOK } from "../../badges/models";
 import { AccessRequestTable } from "../../components/access_requests/AccessRequestTable";
 import { useBackendActor } from "../../context/Global";
 
@@ -21,8 +21,8 @@ export const AccessRequestListPage: React.FC = () => {
 
     RemoteAccessRequestsAPI.getAll()
       .then((response) => {
-        if (isOK(response)) setAccessRequests(response.ok);
-        else setError(response.error);
+        if (isOK(response)) setAccessRequests(response.ok.filter((request) => "pending" in request.status));
+        else setError(errorMessage(response.error));
       })
       .catch((error) => {
         setError(error.message);
@@ -34,13 +34,13 @@ export const AccessRequestListPage: React.FC = () => {
 
   function handleApprove(accessRequestID: bigint) {
     setApproving(true);
-    RemoteAccessRequestsAPI.approveOne(accessRequestID)
+    RemoteAccessRequestsAPI.approveOne(accessRequestID, [], [])
       .then((response) => {
         if (isOK(response)) {
           alert(`Successfully approved access request ${accessRequestID}`);
           loadData();
         } else {
-          setError(response.error);
+          setError(errorMessage(response.error));
         }
       })
       .catch((error) => {
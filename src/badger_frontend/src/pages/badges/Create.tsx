@@ -3,7 +3,15 @@ import { useNavigate } from "react-router-dom";
 import { badgesAPI } from "../../badges/api/remote/badges";
 import { organisationsAPI } from "../../badges/api/remote/organisations";
 import { usersAPI } from "../../badges/api/remote/users";
-import { NewBadgeRequest, Organisation, STUDENT_ROLE_ID, User, isOK } from "../../badges/models";
+import {
+  NewBadgeRequest,
+  Organisation,
+  STUDENT_ROLE_ID,
+  User,
+  errorMessage,
+  fetchAllPages,
+  isOK,
+} from "../../badges/models";
 import { BadgeForm, BadgeFormValues } from "../../components/forms/BadgeForm";
 import { useBackendActor } from "../../context/Global";
 
@@ -38,7 +46,7 @@ export const BadgeCreatePage: React.FC = () => {
       const organisation_id = resp.ok[0].organisationID;
       fetchUsers(organisation_id, STUDENT_ROLE_ID);
     } else {
-      setError(resp.error);
+      setError(errorMessage(resp.error));
     }
     setOrganisationsLoading(() => false);
   }, [RemoteOrganisationsAPI]);
@@ -46,9 +54,9 @@ export const BadgeCreatePage: React.FC = () => {
   const fetchUsers = useCallback(
     async (organisation_id: bigint, role_id: bigint) => {
       setUsersLoading(() => true);
-      const resp = await RemoteUsersAPI.getAll([organisation_id], [role_id]);
+      const resp = await fetchAllPages((page) => RemoteUsersAPI.getAll([organisation_id], [role_id], [], page));
       if (isOK(resp)) setUsers(resp.ok);
-      else setError(resp.error);
+      else setError(errorMessage(resp.error));
       setUsersLoading(() => false);
     },
     [RemoteUsersAPI],
@@ -65,6 +73,7 @@ export const BadgeCreatePage: React.FC = () => {
       description: values.description ? [values.description] : [],
       ownerID: values.ownerID,
       issuerID: BigInt(values.issuerID),
+      expiresAt: [],
     };
 
     console.debug("Submitting payload", payload);
@@ -79,7 +88,7 @@ export const BadgeCreatePage: React.FC = () => {
             navigate("/badges");
           }, 1300);
         } else {
-          setError(resp.error);
+          setError(errorMessage(resp.error));
         }
       })
       .finally(() => {
@@ -1,8 +1,9 @@
 import React, { useEffect } from "react";
 import { useParams } from "react-router-dom";
 import { badgesAPI } from "../../badges/api/remote/badges";
-import { ADMINISTRATION_ROLE_ID, Badge, LECTURER_ROLE_ID, isOK } from "../../badges/models";
+import { ADMINISTRATION_ROLE_ID, Badge, LECTURER_ROLE_ID, errorMessage, isOK } from "../../badges/models";
 import { ProtectedComponent } from "../../components/ProtectedRender";
+import { RevocationForm, RevocationFormValues, toRevocationReason } from "../../components/forms/RevocationForm";
 import { useBackendActor } from "../../context/Global";
 
 export const BadgeDetailsPage: React.FC = () => {
@@ -17,6 +18,7 @@ export const BadgeDetailsPage: React.FC = () => {
 
   const [revoked, setRevoked] = React.useState(false);
   const [revoking, setRevoking] = React.useState(false);
+  const [confirmingRevocation, setConfirmingRevocation] = React.useState(false);
 
   useEffect(() => {
     const badgeID: bigint = BigInt(id);
@@ -24,7 +26,7 @@ export const BadgeDetailsPage: React.FC = () => {
     RemoteBadgesAPI.getOne(badgeID)
       .then((value) => {
         if (isOK(value)) setBadge(value.ok);
-        else setError(value.error);
+        else setError(errorMessage(value.error));
       })
       .catch((error) => {
         setError(error.message);
@@ -34,6 +36,29 @@ export const BadgeDetailsPage: React.FC = () => {
       });
   }, [id]);
 
+  function revoke(badgeID: bigint, values: RevocationFormValues) {
+    setRevoking(true);
+    RemoteBadgesAPI.revokeOne(badgeID, {
+      reason: toRevocationReason(values.reason),
+      details: values.details?.trim() ? [values.details.trim()] : [],
+    })
+      .then((value) => {
+        if (isOK(value)) {
+          setRevoked(true);
+          setConfirmingRevocation(false);
+          setTimeout(() => {
+            window.location.reload();
+          }, 1300);
+        } else setError(errorMessage(value.error));
+      })
+      .catch((error) => {
+        setError(error.message);
+      })
+      .finally(() => {
+        setRevoking(false);
+      });
+  }
+
   if (loading || !badge) {
     return <div>Loading...</div>;
   }
@@ -63,9 +88,9 @@ export const BadgeDetailsPage: React.FC = () => {
               <div className="px-4 py-5 sm:grid sm:grid-cols-3 sm:gap-4 sm:px-6 col-span-3">
                 <dt className="text-sm font-medium text-gray-500">Status</dt>
                 <dd className="mt-1 text-sm text-gray-900 sm:mt-0 sm:col-span-2">
-                  {badge.isRevoked ? (
+                  {!("active" in badge.status) ? (
                     <span className="px-2 inline-flex text-xs leading-5 font-semibold rounded-full bg-red-100 text-red-800">
-                      Revoked
+                      {"suspended" in badge.status ? "Suspended" : "revoked" in badge.status ? "Revoked" : "Expired"}
                     </span>
                   ) : (
                     <span className="px-2 inline-flex text-xs leading-5 font-semibold rounded-full bg-green-100 text-green-800">
@@ -124,31 +149,22 @@ export const BadgeDetailsPage: React.FC = () => {
         </div>
         <ProtectedComponent roles={[ADMINISTRATION_ROLE_ID, LECTURER_ROLE_ID]}>
           <div className="mt-4">
-            <button
-              type="button"
-              className="inline-flex items-center px-4 py-2 border border-transparent text-sm leading-5 font-medium rounded-md text-white bg-red-600 hover:bg-red-500 focus:outline-none focus:shadow-outline-red active:bg-red-700 transition ease-in-out duration-150"
-              disabled={revoking || badge.isRevoked || revoked}
-              onClick={() => {
-                setRevoking(true);
-                RemoteBadgesAPI.revokeOne(badge.badgeID)
-                  .then((value) => {
-                    if (isOK(value)) {
-                      setRevoked(true);
-                      setTimeout(() => {
-                        window.location.reload();
-                      }, 1300);
-                    } else setError(value.error);
-                  })
-                  .catch((error) => {
-                    setError(error.message);
-                  })
-                  .finally(() => {
-                    setRevoking(false);
-                  });
-              }}
-            >
-              Revoke
-            </button>
+            {confirmingRevocation ? (
+              <RevocationForm
+                onSubmit={(values) => revoke(badge.badgeID, values)}
+                onCancel={() => setConfirmingRevocation(false)}
+                disabled={revoking}
+              />
+            ) : (
+              <button
+                type="button"
+                className="inline-flex items-center px-4 py-2 border border-transparent text-sm leading-5 font-medium rounded-md text-white bg-red-600 hover:bg-red-500 focus:outline-none focus:shadow-outline-red active:bg-red-700 transition ease-in-out duration-150"
+                disabled={revoking || "revoked" in badge.status || "expired" in badge.status || revoked}
+                onClick={() => setConfirmingRevocation(true)}
+              >
+                Revoke
+              </button>
+            )}
           </div>
         </ProtectedComponent>
       </div>
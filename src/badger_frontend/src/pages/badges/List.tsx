@@ -1,7 +1,14 @@
 import React, { useEffect, useState } from "react";
 import { Link } from "react-router-dom";
 import { badgesAPI } from "../../badges/api/remote/badges";
-import { ADMINISTRATION_ROLE_ID, Badge, LECTURER_ROLE_ID, isOK } from "../../badges/models";
+import {
+  ADMINISTRATION_ROLE_ID,
+  Badge,
+  LECTURER_ROLE_ID,
+  errorMessage,
+  fetchAllPages,
+  isOK,
+} from "../../badges/models";
 import { ProtectedComponent } from "../../components/ProtectedRender";
 import { BadgeTable } from "../../components/badges/BadgeTable";
 import { useBackendActor } from "../../context/Global";
@@ -17,10 +24,10 @@ export const BadgeListPage: React.FC = () => {
 
   useEffect(() => {
     setLoading(true);
-    RemoteBadgesAPI.getAll([], [])
+    fetchAllPages((page) => RemoteBadgesAPI.getAll([], [], [], page))
       .then((value) => {
         if (isOK(value)) setBadges(value.ok);
-        else setError(value.error);
+        else setError(errorMessage(value.error));
       })
       .catch((error) => {
         setError(error.message);
@@ -3,8 +3,9 @@ import { useParams } from "react-router-dom";
 import { accessRequestsAPI } from "../../badges/api/remote/access_requests";
 import { badgesAPI } from "../../badges/api/remote/badges";
 import { usersAPI } from "../../badges/api/remote/users";
-import { Badge, COMPANY_ROLE_ID, User, isOK } from "../../badges/models";
+import { Badge, COMPANY_ROLE_ID, User, errorMessage, fetchAllPages, isOK } from "../../badges/models";
 import { ProtectedComponent } from "../../components/ProtectedRender";
+import { AccessRequestForm, AccessRequestFormValues, toLegalBasis } from "../../components/forms/AccessRequestForm";
 import { useBackendActor } from "../../context/Global";
 
 export const UserDetailsPage: React.FC = () => {
@@ -22,17 +23,18 @@ export const UserDetailsPage: React.FC = () => {
   const [loading, setLoading] = React.useState(true);
   const [requesting, setRequesting] = React.useState<boolean>(false);
   const [success, setSuccess] = React.useState<boolean>(false);
+  const [requestedBadge, setRequestedBadge] = React.useState<Badge>();
 
   function loadData() {
     setLoading(true);
 
-    Promise.all([RemoteUsersAPI.getOne(id), RemoteBadgesAPI.getAll([id], [])])
+    Promise.all([RemoteUsersAPI.getOne(id), fetchAllPages((page) => RemoteBadgesAPI.getAll([id], [], [], page))])
       .then(([userResponse, badgesResponse]) => {
         if (isOK(userResponse)) setUser(userResponse.ok);
-        else setError(userResponse.error);
+        else setError(errorMessage(userResponse.error));
 
         if (isOK(badgesResponse)) setBadges(badgesResponse.ok);
-        else setError(badgesResponse.error);
+        else setError(errorMessage(badgesResponse.error));
       })
       .catch((error) => {
         setError(error.message);
@@ -42,17 +44,24 @@ export const UserDetailsPage: React.FC = () => {
       });
   }
 
-  function requestAccess(badgeID: bigint) {
+  function requestAccess(badge: Badge, values: AccessRequestFormValues) {
     setRequesting(true);
     setSuccess(false);
     setError(null);
 
-    RemoteAccessRequestsAPI.createOne(badgeID)
+    RemoteAccessRequestsAPI.createOne({
+      badgeID: badge.badgeID,
+      claimKeys: badge.withheldClaimKeys,
+      purpose: values.purpose.trim(),
+      message: values.message?.trim() ? [values.message.trim()] : [],
+      legalBasis: toLegalBasis(values.legalBasis),
+    })
       .then((response) => {
         if (isOK(response)) {
           setSuccess(true);
+          setRequestedBadge(undefined);
         } else {
-          setError(response.error);
+          setError(errorMessage(response.error));
         }
       })
       .catch((error) => {
@@ -92,6 +101,14 @@ export const UserDetailsPage: React.FC = () => {
             <span className="block ml-1 sm:inline">{error}</span>
           </div>
         )}
+        {requestedBadge && (
+          <AccessRequestForm
+            title={requestedBadge.title}
+            onSubmit={(values) => requestAccess(requestedBadge, values)}
+            onCancel={() => setRequestedBadge(undefined)}
+            disabled={requesting}
+          />
+        )}
         <div className="bg-white shadow overflow-hidden sm:rounded-lg">
           <div className="px-4 py-5 sm:px-6">
             <h3 className="text-lg leading-6 font-medium text-gray-900">User Details</h3>
@@ -140,7 +157,10 @@ export const UserDetailsPage: React.FC = () => {
                         </div>
                         <ProtectedComponent roles={[COMPANY_ROLE_ID]}>
                           <div
-                            onClick={() => requestAccess(badge.badgeID)}
+                            onClick={() => {
+                              setSuccess(false);
+                              setRequestedBadge(badge);
+                            }}
                             className="cursor-pointer font-medium text-blue-600 dark:text-blue-500 hover:underline"
                           >
                             Get access
@@ -1,6 +1,6 @@
 import React, { useEffect, useState } from "react";
 import { usersAPI } from "../../badges/api/remote/users";
-import { User, isOK } from "../../badges/models";
+import { User, errorMessage, fetchAllPages, isOK } from "../../badges/models";
 import { UserTable } from "../../components/users/UserTable";
 import { useBackendActor } from "../../context/Global";
 
@@ -15,10 +15,10 @@ export const UserListPage: React.FC = () => {
 
   useEffect(() => {
     setLoading(true);
-    RemoteUsersAPI.getAll([], [])
+    fetchAllPages((page) => RemoteUsersAPI.getAll([], [], [], page))
       .then((value) => {
         if (isOK(value)) setUsers(value.ok);
-        else setError(value.error);
+        else setError(errorMessage(value.error));
       })
       .catch((error) => {
         setError(error.message);
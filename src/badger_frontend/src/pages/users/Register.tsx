@@ -2,7 +2,7 @@ import React, { useEffect, useState } from "react";
 import { useNavigate } from "react-router-dom";
 import { organisationsAPI } from "../../badges/api/remote/organisations";
 import { usersAPI } from "../../badges/api/remote/users";
-import { NewUserRequest, Organisation, Role, isOK } from "../../badges/models";
+import { NewUserRequest, Organisation, Role, errorMessage, isOK } from "../../badges/models";
 import { UserForm, UserFormValues } from "../../components/forms/UserForm";
 import { useBackendActor, useUserSetter } from "../../context/Global";
 
@@ -28,10 +28,10 @@ export const UserRegisterPage: React.FC = () => {
     Promise.all([RemoteOrganisationsAPI.getAll(), RemoteUsersAPI.getAllRoles()])
       .then(([orgResponse, rolesResponse]) => {
         if (isOK(orgResponse)) setOrganisations(orgResponse.ok);
-        else setError(orgResponse.error);
+        else setError(errorMessage(orgResponse.error));
 
         if (isOK(rolesResponse)) setRoles(rolesResponse.ok);
-        else setError(rolesResponse.error);
+        else setError(errorMessage(rolesResponse.error));
       })
       .catch((error) => {
         setError(error.message);
@@ -61,7 +61,7 @@ export const UserRegisterPage: React.FC = () => {
             navigate("/badges");
           }, 1300);
         } else {
-          setError(resp.error);
+          setError(errorMessage(resp.error));
         }
       })
       .finally(() => {